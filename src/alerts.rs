@@ -1,76 +1,102 @@
-use crate::config::Config;
+use crate::attention::AttentionDetection;
+use crate::audio::EndOfMessage;
+use crate::config::{Config, ConfigHandle};
+use crate::eas_text;
 use crate::filter;
+use crate::header::{AlertKey, SameHeader};
+use crate::history::AlertHistory;
 use crate::monitoring::MonitoringHub;
-use crate::recording::{self, RecordingState};
-use crate::relay::RelayState;
+use crate::recording::{self, FinishedRecording, RecordingRegistry};
+use crate::relay::{RelayResult, RelayState};
+use crate::relay_hold::RelayHolds;
+use crate::relay_queue::RelayQueue;
 use crate::state::{ActiveAlert, AppState, EasAlertData};
-use crate::webhook::send_alert_webhook;
+use crate::webhook::{send_alert_webhook, send_relay_notification};
 use anyhow::Result;
 use chrono::Utc;
-use std::collections::HashSet;
-use std::path::{Path, PathBuf};
-use std::process::Command;
+use std::collections::HashMap;
+use std::path::Path;
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::fs;
 use tokio::fs::OpenOptions;
 use tokio::io::AsyncWriteExt;
+use tokio::sync::broadcast::error::RecvError;
 use tokio::sync::broadcast::Receiver as BroadcastReceiver;
 use tokio::sync::{mpsc::Receiver, Mutex};
-use tokio::time::interval;
+use tokio::time::{interval, Instant};
 use tracing::{error, info, instrument, warn};
 
+const MAX_RECORDING_DURATION: Duration = Duration::from_secs(300);
+const ACTIVE_ALERTS_FILE: &str = "active_alerts.json";
 const RAINY_DAY_FILE: &str = "rainy_day.txt";
 const SEVERE_DAY_FILE: &str = "severe_day.txt";
 
-fn is_alert_relevant(alert_data: &EasAlertData, watched_fips: &HashSet<String>) -> bool {
-    if watched_fips.is_empty() {
-        return true;
-    }
-    if alert_data.fips.iter().any(|fips| fips == "000000") {
-        return true;
-    }
-    alert_data
-        .fips
-        .iter()
-        .any(|fips| watched_fips.contains(fips))
-}
-
+#[allow(clippy::too_many_arguments)]
 pub async fn run_alert_manager(
-    config: Config,
+    config_handle: ConfigHandle,
     state: Arc<Mutex<AppState>>,
-    mut rx: Receiver<(String, String, String, String, Duration, String)>,
-    recording_state: Arc<Mutex<Option<RecordingState>>>,
-    nnnn_rx: BroadcastReceiver<()>,
+    mut rx: Receiver<(SameHeader, String)>,
+    recordings: RecordingRegistry,
+    nnnn_rx: BroadcastReceiver<EndOfMessage>,
     monitoring: MonitoringHub,
+    history: AlertHistory,
+    relay_queue: RelayQueue,
+    relay_holds: RelayHolds,
 ) -> Result<()> {
-    while let Some((event, locations, originator, raw_header, purge_time, stream_id)) =
-        rx.recv().await
-    {
-        info!("Processing alert: {}", &raw_header);
-
-        let dsame_result = get_eas_details_and_log(&config, &raw_header).await;
-        let alert_data = match &dsame_result {
-            Ok(data) => data.clone(),
-            Err(_) => EasAlertData {
-                eas_text: "Decoder script failed.".to_string(),
-                event_text: event.clone(),
-                event_code: event,
-                fips: vec![],
-                locations,
-                originator,
-            },
+    let mut recent_headers: HashMap<AlertKey, (Instant, Option<i64>)> = HashMap::new();
+
+    while let Some((header, stream_id)) = rx.recv().await {
+        let config = config_handle.current();
+        let dedup_window = Duration::from_secs(config.alert_dedup_window_secs);
+        recent_headers.retain(|_, (first_seen, _)| first_seen.elapsed() < dedup_window);
+        if let Some((_, history_id)) = recent_headers.get(&header.alert_key()) {
+            info!(
+                stream = %stream_id,
+                "Duplicate alert received from another source: {}", &header
+            );
+            if let Some(id) = *history_id {
+                if let Err(e) = history.add_source(id, &stream_id).await {
+                    error!("Failed to update alert history: {}", e);
+                }
+            }
+            attach_alert_source(&config, &state, &monitoring, &header, &stream_id).await;
+            continue;
+        }
+
+        info!("Processing alert: {}", &header);
+
+        let alert_data = eas_text::translate(&header, config.timezone);
+        if let Err(e) = log_alert_text(&config, &header, &alert_data).await {
+            error!("Failed to write dedicated alert log: {}", e);
+        }
+
+        let purge_time = header.purge_duration();
+        let mut alert = ActiveAlert::new(
+            alert_data.clone(),
+            header.clone(),
+            purge_time,
+            stream_id.clone(),
+        );
+        alert.decision = filter::decide(&config, &alert);
+        info!("Decision for {}: {}", &header, alert.decision);
+        let history_id = match history.record_alert(&alert).await {
+            Ok(id) => Some(id),
+            Err(e) => {
+                error!("Failed to record alert history: {}", e);
+                None
+            }
         };
+        recent_headers.insert(header.alert_key(), (Instant::now(), history_id));
 
-        if is_alert_relevant(&alert_data, &config.watched_fips) {
+        if alert.decision.relevant {
             info!("Alert for watched zone(s) received. Relaying...");
-            let alert = ActiveAlert::new(alert_data.clone(), raw_header.clone(), purge_time);
 
             let active_snapshot = {
                 let mut app_state_guard = state.lock().await;
                 let now = Utc::now();
                 app_state_guard.active_alerts.retain(|existing| {
-                    existing.expires_at > now && existing.raw_header != raw_header
+                    existing.expires_at > now && existing.header.alert_key() != header.alert_key()
                 });
                 app_state_guard.active_alerts.push(alert.clone());
 
@@ -78,26 +104,26 @@ pub async fn run_alert_manager(
                 {
                     error!("Failed to update alert files: {}", e);
                 }
+                if let Err(e) = save_active_alerts(&config.shared_state_dir, &app_state_guard).await
+                {
+                    error!("Failed to save active alerts: {}", e);
+                }
 
                 app_state_guard.active_alerts.clone()
             };
             monitoring.broadcast_alerts(active_snapshot);
 
-            let dsame_text = match dsame_result {
-                Ok(data) => data.eas_text,
-                Err(e) => format!("Decoder script failed: {}", e),
-            };
-
             let value = handle_recording_and_webhook(
                 config.clone(),
-                state.clone(),
-                recording_state.clone(),
+                recordings.clone(),
                 alert,
-                dsame_text,
-                raw_header,
-                purge_time,
+                alert_data.eas_text,
                 stream_id,
                 nnnn_rx.resubscribe(),
+                history.clone(),
+                history_id,
+                relay_queue.clone(),
+                relay_holds.clone(),
             );
 
             tokio::spawn(value);
@@ -111,36 +137,37 @@ pub async fn run_alert_manager(
     Ok(())
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn handle_recording_and_webhook(
-    config: Config,
-    state: Arc<Mutex<AppState>>,
-    recording_state: Arc<Mutex<Option<RecordingState>>>,
+    config: Arc<Config>,
+    recordings: RecordingRegistry,
     alert: ActiveAlert,
     dsame_text: String,
-    raw_header: String,
-    _purge_time: Duration,
     stream_id: String,
-    mut nnnn_rx: BroadcastReceiver<()>,
+    mut nnnn_rx: BroadcastReceiver<EndOfMessage>,
+    history: AlertHistory,
+    history_id: Option<i64>,
+    relay_queue: RelayQueue,
+    relay_holds: RelayHolds,
 ) {
     let event_code = alert.data.event_code.clone();
-    let mut recorded_state: Option<(PathBuf, String)> = None;
-    let mut join_handle: Option<tokio::task::JoinHandle<Result<()>>> = None;
-
-    let mut recorder = recording_state.lock().await;
-    if recorder.is_none() {
-        match recording::start_encoding_task(&config, &raw_header, &stream_id) {
-            Ok((handle, new_state)) => {
-                info!("Recording started for alert: {}", event_code);
-                *recorder = Some(new_state);
-                join_handle = Some(handle);
-            }
-            Err(e) => warn!("Failed to start recording: {}", e),
+    let mut recorded_state: Option<FinishedRecording> = None;
+
+    let recording = match recording::start_encoding_task(&config, &alert.header, &stream_id) {
+        Ok((handle, new_state)) => {
+            info!("Recording started for alert: {}", event_code);
+            let recording_id = new_state.id;
+            recordings.insert(new_state);
+            Some((handle, recording_id))
         }
-    }
-    drop(recorder);
+        Err(e) => {
+            warn!("Failed to start recording: {}", e);
+            None
+        }
+    };
 
-    if let Some(handle) = join_handle {
-        let sleep_duration = Duration::from_secs(300);
+    if let Some((handle, recording_id)) = recording {
+        let sleep_duration = recording_timeout(alert.purge_time);
         info!(
             "Waiting for alert to end ({}s timeout or NNNN)...",
             sleep_duration.as_secs()
@@ -150,25 +177,29 @@ async fn handle_recording_and_webhook(
             _ = tokio::time::sleep(sleep_duration) => {
                 info!("Recording timer expired for alert: {}", event_code);
             }
-            res = nnnn_rx.recv() => {
-                if res.is_ok() {
-                    info!("NNNN received, stopping recording for alert: {}", event_code);
-                } else {
-                    warn!("NNNN broadcast channel closed.");
+            eom = wait_for_end_of_message(&mut nnnn_rx, &stream_id) => {
+                match eom {
+                    Some(eom) => {
+                        info!(
+                            stream = %stream_id,
+                            "NNNN received, stopping recording for alert: {}{}",
+                            event_code,
+                            eom.header
+                                .filter(|closed| *closed != alert.header)
+                                .map(|closed| format!(" (closing {})", closed))
+                                .unwrap_or_default()
+                        );
+                        tokio::time::sleep(Duration::from_secs(config.recording_postroll_secs)).await;
+                    }
+                    None => warn!("NNNN broadcast channel closed."),
                 }
             }
         }
 
         info!("Stopping recording for alert: {}", event_code);
 
-        if let Some(RecordingState {
-            audio_tx,
-            output_path,
-            source_stream,
-        }) = recording_state.lock().await.take()
-        {
-            drop(audio_tx);
-            recorded_state = Some((output_path, source_stream));
+        if let Some(finished) = recordings.remove(recording_id) {
+            recorded_state = Some(finished.finish());
         } else {
             warn!(
                 "Recording state missing when finalizing alert {}",
@@ -181,58 +212,277 @@ async fn handle_recording_and_webhook(
         }
     }
 
-    if filter::should_log_alert(&event_code) {
-        let recording_path_for_webhook = recorded_state.as_ref().map(|(path, _)| path.clone());
-        send_alert_webhook(
+    if let (Some(id), Some(recorded)) = (history_id, recorded_state.as_ref()) {
+        if let Err(e) = history.set_recording_path(id, &recorded.path).await {
+            error!("Failed to update alert history: {}", e);
+        }
+    }
+
+    let mut notifications = Vec::new();
+    if alert.decision.notify {
+        let recording_path_for_webhook = recorded_state
+            .as_ref()
+            .map(|recorded| recorded.path.clone());
+        notifications = send_alert_webhook(
+            &config,
             &stream_id,
             &alert,
             &dsame_text,
-            &raw_header,
             recording_path_for_webhook,
         )
         .await;
+        if let Some(id) = history_id {
+            if let Err(e) = history.set_notifications(id, &notifications).await {
+                error!("Failed to update alert history: {}", e);
+            }
+        }
     }
 
     if config.should_relay {
-        if let Some((ref recording_path, ref source_stream)) = recorded_state {
-            let filters = {
-                let guard = state.lock().await;
-                guard.cloned_filters()
-            };
+        let relay_results = relay_alert(
+            &config,
+            &alert,
+            recorded_state.as_ref(),
+            relay_queue,
+            relay_holds,
+        )
+        .await;
+        if let Some(id) = history_id {
+            if let Err(e) = history.set_relay_results(id, &relay_results).await {
+                error!("Failed to update alert history: {}", e);
+            }
+        }
 
-            let relay_state = match RelayState::new(config.clone()).await {
-                Ok(state) => state,
-                Err(err) => {
-                    warn!("Skipping relay due to configuration error: {:?}", err);
-                    return;
+        // Relays can be held for approval, so their results follow the
+        // alert notification in a notice of their own.
+        if alert.decision.notify && !relay_results.is_empty() {
+            notifications.extend(send_relay_notification(&config, &alert, &relay_results).await);
+            if let Some(id) = history_id {
+                if let Err(e) = history.set_notifications(id, &notifications).await {
+                    error!("Failed to update alert history: {}", e);
                 }
+            }
+        }
+    }
+}
+
+async fn relay_alert(
+    config: &Arc<Config>,
+    alert: &ActiveAlert,
+    recorded_state: Option<&FinishedRecording>,
+    relay_queue: RelayQueue,
+    relay_holds: RelayHolds,
+) -> Vec<RelayResult> {
+    let fail_all = |detail: &str| {
+        config
+            .relay_destinations
+            .iter()
+            .map(|destination| RelayResult::failed(&destination.name, detail))
+            .collect()
+    };
+    let Some(recording) = recorded_state else {
+        warn!("No completed recording available for relay; skipping Icecast relay.");
+        return fail_all("no recording");
+    };
+
+    let relay_state = match RelayState::new(config.clone(), relay_queue, relay_holds).await {
+        Ok(state) => state,
+        Err(err) => {
+            warn!("Skipping relay due to configuration error: {:?}", err);
+            return fail_all(&err.to_string());
+        }
+    };
+
+    // Destinations relay in parallel; results are reported in config order.
+    let tasks: Vec<_> = config
+        .relay_destinations
+        .iter()
+        .map(|destination| {
+            let relay_state = relay_state.clone();
+            let destination = destination.clone();
+            let alert = alert.clone();
+            let recording = recording.clone();
+            tokio::spawn(async move {
+                let result = match relay_state
+                    .start_relay(&destination, &alert, &recording)
+                    .await
+                {
+                    Ok(outcome) => RelayResult::from_outcome(&destination.name, outcome),
+                    Err(err) => {
+                        warn!(
+                            destination = %destination.name,
+                            "Icecast relay failed: {:#}", err
+                        );
+                        RelayResult::failed(&destination.name, err.to_string())
+                    }
+                };
+                info!(destination = %destination.name, "Relay result: {}", result);
+                result
+            })
+        })
+        .collect();
+
+    let mut results = Vec::with_capacity(tasks.len());
+    for (task, destination) in tasks.into_iter().zip(&config.relay_destinations) {
+        results.push(task.await.unwrap_or_else(|err| {
+            RelayResult::failed(&destination.name, format!("relay task panicked: {}", err))
+        }));
+    }
+    results
+}
+
+async fn attach_alert_source(
+    config: &Config,
+    state: &Arc<Mutex<AppState>>,
+    monitoring: &MonitoringHub,
+    header: &SameHeader,
+    stream_id: &str,
+) {
+    let active_snapshot = {
+        let mut app_state_guard = state.lock().await;
+        let added = app_state_guard
+            .active_alerts
+            .iter_mut()
+            .find(|existing| existing.header.alert_key() == header.alert_key())
+            .is_some_and(|existing| existing.add_source(stream_id));
+        if !added {
+            return;
+        }
+        if let Err(e) = save_active_alerts(&config.shared_state_dir, &app_state_guard).await {
+            error!("Failed to save active alerts: {}", e);
+        }
+        app_state_guard.active_alerts.clone()
+    };
+    monitoring.broadcast_alerts(active_snapshot);
+}
+
+/// Attaches attention signals to the alert whose header was open when they
+/// played, and reports tone-only signals, which point to a missed header
+/// decode or a manual activation.
+pub async fn run_attention_monitor(
+    config_handle: ConfigHandle,
+    state: Arc<Mutex<AppState>>,
+    mut rx: Receiver<AttentionDetection>,
+    monitoring: MonitoringHub,
+) -> Result<()> {
+    while let Some(detection) = rx.recv().await {
+        let tone = detection.tone.label();
+        let duration = format!("{:.1}s", detection.duration_secs);
+        let Some(header) = detection.header.clone() else {
+            warn!(
+                stream = %detection.stream,
+                tone,
+                duration = %duration,
+                "Attention signal without a SAME header (missed header decode or manual activation)"
+            );
+            monitoring.record_attention(detection);
+            continue;
+        };
+        info!(
+            stream = %detection.stream,
+            tone,
+            duration = %duration,
+            "Attention signal detected for {}", header
+        );
+
+        let config = config_handle.current();
+        // The tone may come from a second source whose header carries
+        // another station ID, so match on the alert key.
+        let key = SameHeader::parse(&header)
+            .ok()
+            .map(|header| header.alert_key());
+        let active_snapshot = {
+            let mut app_state_guard = state.lock().await;
+            let Some(alert) = app_state_guard
+                .active_alerts
+                .iter_mut()
+                .find(|alert| key.as_ref() == Some(&alert.header.alert_key()))
+            else {
+                drop(app_state_guard);
+                monitoring.record_attention(detection);
+                continue;
             };
+            alert.attention.push(detection.clone());
+            if let Err(e) = save_active_alerts(&config.shared_state_dir, &app_state_guard).await {
+                error!("Failed to save active alerts: {}", e);
+            }
+            app_state_guard.active_alerts.clone()
+        };
+        monitoring.broadcast_alerts(active_snapshot);
+        monitoring.record_attention(detection);
+    }
+    Ok(())
+}
 
-            if let Err(err) = relay_state
-                .start_relay(
-                    event_code.as_str(),
-                    filters.as_slice(),
-                    recording_path,
-                    Some(source_stream.as_str()),
-                )
-                .await
-            {
-                warn!("FFmpeg relay failed: {:?}", err);
+/// Alerts shorter than the recording cap stop recording when they purge.
+pub(crate) fn recording_timeout(purge_time: Duration) -> Duration {
+    if purge_time.is_zero() {
+        MAX_RECORDING_DURATION
+    } else {
+        purge_time.min(MAX_RECORDING_DURATION)
+    }
+}
+
+async fn wait_for_end_of_message(
+    nnnn_rx: &mut BroadcastReceiver<EndOfMessage>,
+    stream_id: &str,
+) -> Option<EndOfMessage> {
+    loop {
+        match nnnn_rx.recv().await {
+            Ok(eom) if eom.stream_id == stream_id => return Some(eom),
+            Ok(_) => continue,
+            Err(RecvError::Lagged(skipped)) => {
+                warn!(
+                    "Missed {} NNNN event(s) while waiting for end of alert",
+                    skipped
+                );
             }
-        } else {
-            warn!("No completed recording available for relay; skipping FFmpeg relay.");
+            Err(RecvError::Closed) => return None,
         }
     }
 }
 
+pub async fn restore_active_alerts(
+    config: &Config,
+    state: &Arc<Mutex<AppState>>,
+    monitoring: &MonitoringHub,
+) -> Result<()> {
+    let path = config.shared_state_dir.join(ACTIVE_ALERTS_FILE);
+    let mut alerts: Vec<ActiveAlert> = match fs::read_to_string(&path).await {
+        Ok(contents) => serde_json::from_str(&contents)?,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+        Err(e) => return Err(e.into()),
+    };
+
+    let now = Utc::now();
+    let saved_count = alerts.len();
+    alerts.retain(|alert| alert.expires_at > now);
+    info!(
+        "Restored {} active alert(s) from disk ({} expired).",
+        alerts.len(),
+        saved_count - alerts.len()
+    );
+
+    let mut app_state_guard = state.lock().await;
+    app_state_guard.active_alerts = alerts;
+    update_alert_files(&config.shared_state_dir, &app_state_guard).await?;
+    save_active_alerts(&config.shared_state_dir, &app_state_guard).await?;
+    let alert_snapshot = app_state_guard.active_alerts.clone();
+    drop(app_state_guard);
+
+    monitoring.broadcast_alerts(alert_snapshot);
+    Ok(())
+}
+
 pub async fn run_state_cleanup(
-    config: Config,
+    config_handle: ConfigHandle,
     state: Arc<Mutex<AppState>>,
     monitoring: MonitoringHub,
 ) -> Result<()> {
     let mut timer = interval(Duration::from_secs(60));
     loop {
         timer.tick().await;
+        let config = config_handle.current();
 
         let mut app_state_guard = state.lock().await;
         let initial_count = app_state_guard.active_alerts.len();
@@ -247,6 +497,9 @@ pub async fn run_state_cleanup(
             if let Err(e) = update_alert_files(&config.shared_state_dir, &app_state_guard).await {
                 error!("Failed to update alert files after cleanup: {}", e);
             }
+            if let Err(e) = save_active_alerts(&config.shared_state_dir, &app_state_guard).await {
+                error!("Failed to save active alerts after cleanup: {}", e);
+            }
         }
 
         let alert_snapshot = app_state_guard.active_alerts.clone();
@@ -258,43 +511,36 @@ pub async fn run_state_cleanup(
     }
 }
 
-async fn get_eas_details_and_log(config: &Config, raw_header: &str) -> Result<EasAlertData> {
-    let header_clone = raw_header.to_string();
-    let timezone = config.timezone.clone().to_string();
-    let output = tokio::task::spawn_blocking(move || {
-        Command::new("python3")
-            .arg("/usr/local/bin/decoder.py")
-            .arg("--msg")
-            .arg(header_clone)
-            .arg("--tz")
-            .arg(timezone)
-            .output()
-    })
-    .await??;
-
-    if output.status.success() {
-        let alert_data: EasAlertData = serde_json::from_slice(&output.stdout)?;
-
-        let received_at = Utc::now();
-        let local_time = received_at.with_timezone(&config.timezone);
-        let timestamp = local_time.format("%Y-%m-%d %l:%M:%S %p");
-        let log_line = format!(
-            "{}: {} (Received @ {})\n\n",
-            raw_header, alert_data.eas_text, timestamp
-        );
+async fn log_alert_text(
+    config: &Config,
+    header: &SameHeader,
+    alert_data: &EasAlertData,
+) -> Result<()> {
+    let received_at = Utc::now();
+    let local_time = received_at.with_timezone(&config.timezone);
+    let timestamp = local_time.format("%Y-%m-%d %l:%M:%S %p");
+    let log_line = format!(
+        "{}: {} (Received @ {})\n\n",
+        header, alert_data.eas_text, timestamp
+    );
+
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&config.dedicated_alert_log_file)
+        .await?;
+    file.write_all(log_line.as_bytes()).await?;
 
-        let mut file = OpenOptions::new()
-            .create(true)
-            .append(true)
-            .open(&config.dedicated_alert_log_file)
-            .await?;
-        file.write_all(log_line.as_bytes()).await?;
+    Ok(())
+}
 
-        Ok(alert_data)
-    } else {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        anyhow::bail!("decoder.py script failed: {}", stderr);
-    }
+async fn save_active_alerts(state_dir: &Path, app_state: &AppState) -> Result<()> {
+    let path = state_dir.join(ACTIVE_ALERTS_FILE);
+    let temp_path = path.with_extension("json.tmp");
+    let contents = serde_json::to_vec_pretty(&app_state.active_alerts)?;
+    fs::write(&temp_path, contents).await?;
+    fs::rename(&temp_path, &path).await?;
+    Ok(())
 }
 
 #[instrument(skip(state_dir, app_state))]
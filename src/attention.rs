@@ -0,0 +1,297 @@
+use crate::header::AttentionTone;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::f32::consts::PI;
+use std::time::Duration;
+
+/// Analysis block length. 50 ms gives 20 Hz resolution, enough to tell 853,
+/// 960 and 1050 Hz apart.
+const BLOCK_DURATION: Duration = Duration::from_millis(50);
+/// Blocks quieter than this (RMS 0.01, about -40 dBFS) are never a tone.
+const MIN_BLOCK_POWER: f32 = 1.0e-4;
+/// Share of a block's energy that must sit on the tone frequencies.
+const MIN_TONE_SHARE: f32 = 0.75;
+/// Each half of the two-tone must carry at least this share on its own.
+const MIN_TWO_TONE_COMPONENT: f32 = 0.25;
+/// Dropouts up to this long do not end a tone.
+const HANGOVER: Duration = Duration::from_millis(250);
+/// Tones shorter than this are not reported.
+const MIN_DURATION: Duration = Duration::from_secs(2);
+
+const TWO_TONE_LOW: f32 = 853.0;
+const TWO_TONE_HIGH: f32 = 960.0;
+const NWR_TONE: f32 = 1050.0;
+
+/// An attention signal heard on a monitored stream.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AttentionDetection {
+    pub tone: AttentionTone,
+    pub stream: String,
+    #[serde(with = "chrono::serde::ts_milliseconds")]
+    pub started_at: DateTime<Utc>,
+    pub duration_secs: f64,
+    /// The SAME header open on the stream when the tone was heard; `None`
+    /// for a tone without a decoded header.
+    pub header: Option<String>,
+}
+
+/// A finished tone, in samples counted from the start of the stream.
+#[derive(Debug, Clone, Copy)]
+pub struct AttentionSignal {
+    pub tone: AttentionTone,
+    pub start: u64,
+    pub length: u64,
+}
+
+impl AttentionSignal {
+    pub fn duration(&self, sample_rate: u32) -> Duration {
+        Duration::from_secs_f64(self.length as f64 / sample_rate as f64)
+    }
+}
+
+struct Goertzel {
+    coeff: f32,
+    s1: f32,
+    s2: f32,
+}
+
+impl Goertzel {
+    fn new(freq: f32, sample_rate: u32) -> Self {
+        Self {
+            coeff: 2.0 * (2.0 * PI * freq / sample_rate as f32).cos(),
+            s1: 0.0,
+            s2: 0.0,
+        }
+    }
+
+    fn push(&mut self, sample: f32) {
+        let s0 = sample + self.coeff * self.s1 - self.s2;
+        self.s2 = self.s1;
+        self.s1 = s0;
+    }
+
+    /// Squared magnitude at the filter frequency, resetting the filter.
+    fn take_power(&mut self) -> f32 {
+        let power = self.s1 * self.s1 + self.s2 * self.s2 - self.coeff * self.s1 * self.s2;
+        self.s1 = 0.0;
+        self.s2 = 0.0;
+        power
+    }
+}
+
+struct ToneRun {
+    tone: AttentionTone,
+    start: u64,
+    end: u64,
+}
+
+/// Goertzel detector for the EAS two-tone (853 + 960 Hz) and the NOAA
+/// Weather Radio 1050 Hz attention signals.
+pub struct AttentionDetector {
+    block_len: usize,
+    hangover: u64,
+    min_length: u64,
+    low: Goertzel,
+    high: Goertzel,
+    nwr: Goertzel,
+    block_fill: usize,
+    block_energy: f32,
+    position: u64,
+    run: Option<ToneRun>,
+}
+
+impl AttentionDetector {
+    pub fn new(sample_rate: u32) -> Self {
+        let samples = |duration: Duration| (duration.as_secs_f64() * sample_rate as f64) as u64;
+        Self {
+            block_len: samples(BLOCK_DURATION).max(1) as usize,
+            hangover: samples(HANGOVER),
+            min_length: samples(MIN_DURATION),
+            low: Goertzel::new(TWO_TONE_LOW, sample_rate),
+            high: Goertzel::new(TWO_TONE_HIGH, sample_rate),
+            nwr: Goertzel::new(NWR_TONE, sample_rate),
+            block_fill: 0,
+            block_energy: 0.0,
+            position: 0,
+            run: None,
+        }
+    }
+
+    /// Feeds `samples` and returns every tone that ended within them.
+    pub fn process(&mut self, samples: &[f32]) -> Vec<AttentionSignal> {
+        let mut finished = Vec::new();
+        for &sample in samples {
+            self.low.push(sample);
+            self.high.push(sample);
+            self.nwr.push(sample);
+            self.block_energy += sample * sample;
+            self.block_fill += 1;
+            self.position += 1;
+
+            if self.block_fill == self.block_len {
+                let tone = self.classify_block();
+                if let Some(signal) = self.track(tone) {
+                    finished.push(signal);
+                }
+            }
+        }
+        finished
+    }
+
+    fn classify_block(&mut self) -> Option<AttentionTone> {
+        let n = self.block_len as f32;
+        let energy = std::mem::take(&mut self.block_energy);
+        self.block_fill = 0;
+        // A full-scale sine of amplitude A has Goertzel power (A·N/2)² and
+        // block energy A²·N/2, so this share is 1.0 for a pure tone.
+        let share = |power: f32| 2.0 * power / (n * energy);
+        let low = share(self.low.take_power());
+        let high = share(self.high.take_power());
+        let nwr = share(self.nwr.take_power());
+
+        if energy / n < MIN_BLOCK_POWER {
+            return None;
+        }
+        if low >= MIN_TWO_TONE_COMPONENT
+            && high >= MIN_TWO_TONE_COMPONENT
+            && low + high >= MIN_TONE_SHARE
+        {
+            return Some(AttentionTone::TwoTone);
+        }
+        if nwr >= MIN_TONE_SHARE {
+            return Some(AttentionTone::Nwr);
+        }
+        None
+    }
+
+    fn track(&mut self, tone: Option<AttentionTone>) -> Option<AttentionSignal> {
+        let block_start = self.position - self.block_len as u64;
+        match (&mut self.run, tone) {
+            (Some(run), Some(tone)) if run.tone == tone => {
+                run.end = self.position;
+                return None;
+            }
+            (Some(run), None) if self.position - run.end <= self.hangover => return None,
+            _ => {}
+        }
+
+        let finished = self.run.take().and_then(|run| {
+            let length = run.end - run.start;
+            (length >= self.min_length).then_some(AttentionSignal {
+                tone: run.tone,
+                start: run.start,
+                length,
+            })
+        });
+        self.run = tone.map(|tone| ToneRun {
+            tone,
+            start: block_start,
+            end: self.position,
+        });
+        finished
+    }
+
+    /// Samples fed so far.
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::header::generate_attention_tone;
+
+    const SAMPLE_RATE: u32 = 48000;
+
+    fn tone(tone: AttentionTone, duration: Duration) -> Vec<f32> {
+        generate_attention_tone(tone, duration, SAMPLE_RATE, 0.5)
+            .unwrap()
+            .into_iter()
+            .map(|s| s as f32 / i16::MAX as f32)
+            .collect()
+    }
+
+    fn silence(duration: Duration) -> Vec<f32> {
+        vec![0.0; (duration.as_secs_f64() * SAMPLE_RATE as f64) as usize]
+    }
+
+    /// Feeds `audio` in uneven chunks, followed by a second of silence so
+    /// the last tone ends.
+    fn detect(mut audio: Vec<f32>) -> Vec<AttentionSignal> {
+        audio.extend(silence(Duration::from_secs(1)));
+        let mut detector = AttentionDetector::new(SAMPLE_RATE);
+        audio
+            .chunks(1237)
+            .flat_map(|chunk| detector.process(chunk))
+            .collect()
+    }
+
+    fn assert_duration(signal: &AttentionSignal, expected: f64) {
+        let heard = signal.duration(SAMPLE_RATE).as_secs_f64();
+        assert!(
+            (heard - expected).abs() <= 0.1,
+            "heard {heard:.3} s, expected {expected} s"
+        );
+    }
+
+    #[test]
+    fn detects_the_two_tone() {
+        let mut audio = silence(Duration::from_millis(500));
+        audio.extend(tone(AttentionTone::TwoTone, Duration::from_secs(8)));
+        let signals = detect(audio);
+        assert_eq!(signals.len(), 1);
+        assert_eq!(signals[0].tone, AttentionTone::TwoTone);
+        assert_duration(&signals[0], 8.0);
+        let start = signals[0].start as f64 / SAMPLE_RATE as f64;
+        assert!((start - 0.5).abs() <= 0.1, "started at {start:.3} s");
+    }
+
+    #[test]
+    fn detects_the_nwr_tone() {
+        let signals = detect(tone(AttentionTone::Nwr, Duration::from_secs(8)));
+        assert_eq!(signals.len(), 1);
+        assert_eq!(signals[0].tone, AttentionTone::Nwr);
+        assert_duration(&signals[0], 8.0);
+    }
+
+    #[test]
+    fn ignores_short_tones() {
+        let mut audio = tone(AttentionTone::TwoTone, Duration::from_secs(1));
+        audio.extend(silence(Duration::from_secs(1)));
+        audio.extend(tone(AttentionTone::Nwr, Duration::from_millis(1500)));
+        assert!(detect(audio).is_empty());
+    }
+
+    #[test]
+    fn bridges_short_dropouts() {
+        let mut audio = tone(AttentionTone::TwoTone, Duration::from_secs(3));
+        audio.extend(silence(Duration::from_millis(100)));
+        audio.extend(tone(AttentionTone::TwoTone, Duration::from_secs(3)));
+        let signals = detect(audio);
+        assert_eq!(signals.len(), 1);
+        assert_duration(&signals[0], 6.1);
+    }
+
+    #[test]
+    fn splits_on_long_gaps() {
+        let mut audio = tone(AttentionTone::Nwr, Duration::from_secs(3));
+        audio.extend(silence(Duration::from_millis(600)));
+        audio.extend(tone(AttentionTone::Nwr, Duration::from_secs(4)));
+        let signals = detect(audio);
+        assert_eq!(signals.len(), 2);
+        assert_duration(&signals[0], 3.0);
+        assert_duration(&signals[1], 4.0);
+        assert!(signals[1].start > signals[0].start + signals[0].length);
+    }
+
+    #[test]
+    fn ignores_silence_and_other_tones() {
+        let rate = SAMPLE_RATE as f32;
+        let audio: Vec<f32> = (0..SAMPLE_RATE * 5)
+            .map(|n| 0.5 * (2.0 * PI * 1500.0 * n as f32 / rate).sin())
+            .chain(silence(Duration::from_secs(5)))
+            .collect();
+        assert!(detect(audio).is_empty());
+    }
+}
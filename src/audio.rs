@@ -1,34 +1,124 @@
-use crate::config::Config;
+use crate::alerts;
+use crate::attention::{AttentionDetection, AttentionDetector, AttentionSignal};
+use crate::config::ConfigHandle;
+use crate::header::SameHeader;
+use crate::levels::{LevelMeter, LevelReading};
+use crate::media::decode_media;
 use crate::monitoring::MonitoringHub;
-use crate::recording::RecordingState;
+use crate::recording::{RecordingMarker, RecordingRegistry, RecordingState};
 use anyhow::{anyhow, Context, Result};
 use bytes::Bytes;
-use rubato::{Resampler, SincFixedIn};
-use sameold::{Message as SameMessage, SameReceiverBuilder};
+use chrono::Utc;
+use parking_lot::Mutex;
+use sameold::{LinkState, Message as SameMessage, SameEventType, SameReceiverBuilder};
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, VecDeque};
 use std::future::pending;
 use std::io::{Read, Result as IoResult};
 use std::sync::Arc;
 use std::time::Duration;
-use symphonia::core::audio::SampleBuffer;
-use symphonia::core::codecs::DecoderOptions;
-use symphonia::core::errors::Error as SymphoniaError;
-use symphonia::core::formats::FormatOptions;
 use symphonia::core::io::{MediaSourceStream, ReadOnlySource};
-use symphonia::core::meta::MetadataOptions;
 use symphonia::core::probe::Hint;
 use tokio::sync::broadcast::Sender as BroadcastSender;
 use tokio::sync::mpsc::error::TrySendError;
-use tokio::sync::mpsc::Sender as TokioSender;
-use tokio::sync::Mutex;
+use tokio::sync::mpsc::{Receiver as TokioReceiver, Sender as TokioSender};
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
 use tokio::time::Instant;
 use tracing::{error, info, warn};
 
-const TARGET_SAMPLE_RATE: u32 = 48000;
+pub(crate) use crate::media::TARGET_SAMPLE_RATE;
 
 fn stream_inactivity_timeout() -> std::time::Duration {
     std::time::Duration::from_secs(120)
 }
 
+/// An NNNN decoded on a stream, along with the header it most likely closes.
+#[derive(Debug, Clone)]
+pub struct EndOfMessage {
+    pub stream_id: String,
+    pub header: Option<SameHeader>,
+}
+
+type TapSender = TokioSender<Vec<f32>>;
+
+/// Live copies of decoded stream audio, used to feed a continuous relay with
+/// a monitor's program audio. Publishing is free when nobody is listening.
+#[derive(Debug, Clone, Default)]
+pub struct StreamTaps {
+    taps: Arc<Mutex<HashMap<String, Vec<TapSender>>>>,
+}
+
+impl StreamTaps {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Receives every chunk decoded from `stream` at [`TARGET_SAMPLE_RATE`]
+    /// until the receiver is dropped.
+    pub fn subscribe(&self, stream: &str) -> TokioReceiver<Vec<f32>> {
+        let (tx, rx) = tokio::sync::mpsc::channel(64);
+        self.taps
+            .lock()
+            .entry(stream.to_string())
+            .or_default()
+            .push(tx);
+        rx
+    }
+
+    fn publish(&self, stream: &str, samples: &[f32]) {
+        let mut taps = self.taps.lock();
+        let Some(senders) = taps.get_mut(stream) else {
+            return;
+        };
+        // A full tap means its reader fell behind; it is better to drop audio
+        // than to stall decoding.
+        senders.retain(|tx| !matches!(tx.try_send(samples.to_vec()), Err(TrySendError::Closed(_))));
+        if senders.is_empty() {
+            taps.remove(stream);
+        }
+    }
+}
+
+/// Holds the most recent audio of a stream so a recording can include what was
+/// heard before the alert manager got around to starting it.
+struct PreRollBuffer {
+    samples: VecDeque<f32>,
+    capacity: usize,
+}
+
+impl PreRollBuffer {
+    fn new(seconds: u64) -> Self {
+        let capacity = seconds as usize * TARGET_SAMPLE_RATE as usize;
+        Self {
+            samples: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    fn push(&mut self, chunk: &[f32]) {
+        if self.capacity == 0 {
+            return;
+        }
+        if chunk.len() >= self.capacity {
+            self.samples.clear();
+            self.samples.extend(&chunk[chunk.len() - self.capacity..]);
+            return;
+        }
+        let overflow = (self.samples.len() + chunk.len()).saturating_sub(self.capacity);
+        self.samples.drain(..overflow);
+        self.samples.extend(chunk);
+    }
+
+    fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    fn snapshot(&self) -> Vec<f32> {
+        self.samples.iter().copied().collect()
+    }
+}
+
 struct ChannelReader {
     rx: crossbeam_channel::Receiver<Bytes>,
     buffer: Bytes,
@@ -54,11 +144,17 @@ impl Read for ChannelReader {
     }
 }
 
+/// Runs one decoder task per configured stream, starting and stopping tasks as
+/// the stream list changes across config reloads.
+#[allow(clippy::too_many_arguments)]
 pub async fn run_audio_processor(
-    config: Config,
-    tx: TokioSender<(String, String, String, String, Duration, String)>,
-    recording_state: Arc<Mutex<Option<RecordingState>>>,
-    nnnn_tx: BroadcastSender<()>,
+    config: ConfigHandle,
+    tx: TokioSender<(SameHeader, String)>,
+    recordings: RecordingRegistry,
+    taps: StreamTaps,
+    nnnn_tx: BroadcastSender<EndOfMessage>,
+    attention_tx: TokioSender<AttentionDetection>,
+    levels_tx: TokioSender<LevelReading>,
     monitoring: MonitoringHub,
 ) -> Result<()> {
     let client = reqwest::Client::builder()
@@ -69,45 +165,85 @@ pub async fn run_audio_processor(
         .build()
         .context("build reqwest client")?;
 
-    for stream_url in config.icecast_stream_urls {
-        let client_clone = client.clone();
-        let tx_clone = tx.clone();
-        let recording_state_clone = recording_state.clone();
-        let nnnn_tx_clone = nnnn_tx.clone();
-        let monitoring_clone = monitoring.clone();
-
-        tokio::spawn(async move {
-            let stream_for_log = stream_url.clone();
-            if let Err(e) = run_stream_task(
-                stream_url,
-                client_clone,
-                tx_clone,
-                recording_state_clone,
-                nnnn_tx_clone,
-                monitoring_clone,
-            )
-            .await
-            {
-                error!(stream = %stream_for_log, "Stream task terminated: {e:?}");
+    let mut updates = config.subscribe();
+    let mut stream_tasks: HashMap<String, JoinHandle<()>> = HashMap::new();
+
+    loop {
+        let current = updates.borrow_and_update().config.clone();
+        let preroll_secs = current.recording_preroll_secs;
+
+        stream_tasks.retain(|stream_url, task| {
+            if current.icecast_stream_urls.contains(stream_url) {
+                return true;
             }
+            info!(stream = %stream_url, "Stream removed from config; stopping monitor");
+            task.abort();
+            monitoring.remove_stream(stream_url);
+            false
         });
-    }
 
-    drop(tx);
-    drop(nnnn_tx);
+        for stream_url in &current.icecast_stream_urls {
+            if stream_tasks.contains_key(stream_url) {
+                continue;
+            }
+
+            let stream_url = stream_url.clone();
+            let client_clone = client.clone();
+            let tx_clone = tx.clone();
+            let recordings_clone = recordings.clone();
+            let taps_clone = taps.clone();
+            let nnnn_tx_clone = nnnn_tx.clone();
+            let attention_tx_clone = attention_tx.clone();
+            let levels_tx_clone = levels_tx.clone();
+            let monitoring_clone = monitoring.clone();
+
+            let task = tokio::spawn({
+                let stream_url = stream_url.clone();
+                async move {
+                    let stream_for_log = stream_url.clone();
+                    if let Err(e) = run_stream_task(
+                        stream_url,
+                        client_clone,
+                        tx_clone,
+                        recordings_clone,
+                        taps_clone,
+                        nnnn_tx_clone,
+                        attention_tx_clone,
+                        levels_tx_clone,
+                        monitoring_clone,
+                        preroll_secs,
+                    )
+                    .await
+                    {
+                        error!(stream = %stream_for_log, "Stream task terminated: {e:?}");
+                    }
+                }
+            });
+            stream_tasks.insert(stream_url, task);
+        }
+
+        if updates.changed().await.is_err() {
+            break;
+        }
+    }
 
     pending::<()>().await;
     #[allow(unreachable_code)]
     Ok(())
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn run_stream_task(
     stream_url: String,
     client: reqwest::Client,
-    tx: TokioSender<(String, String, String, String, Duration, String)>,
-    recording_state: Arc<Mutex<Option<RecordingState>>>,
-    nnnn_tx: BroadcastSender<()>,
+    tx: TokioSender<(SameHeader, String)>,
+    recordings: RecordingRegistry,
+    taps: StreamTaps,
+    nnnn_tx: BroadcastSender<EndOfMessage>,
+    attention_tx: TokioSender<AttentionDetection>,
+    levels_tx: TokioSender<LevelReading>,
     monitoring: MonitoringHub,
+    preroll_secs: u64,
 ) -> Result<()> {
     let mut last_log_time = Instant::now() - Duration::from_secs(61);
     let mut last_log_time2 = Instant::now() - Duration::from_secs(61);
@@ -155,18 +291,25 @@ async fn run_stream_task(
                     .map(String::from);
 
                 let (byte_tx, byte_rx) = crossbeam_channel::bounded::<Bytes>(256);
+                // Dropped when this connection ends or the task is aborted
+                // because the stream left the config; the reader and the
+                // decoder both stop when it goes away.
+                let (_stop_guard, stop) = watch::channel(());
 
                 let stream_for_reader = stream_url.clone();
                 let monitoring_reader = monitoring.clone();
+                let mut stop_reader = stop.clone();
                 tokio::spawn(async move {
                     let mut response = response;
 
                     let mut last_warn = std::time::Instant::now();
 
                     loop {
-                        match tokio::time::timeout(stream_inactivity_timeout(), response.chunk())
-                            .await
-                        {
+                        let chunk = tokio::select! {
+                            _ = stop_reader.changed() => break,
+                            chunk = tokio::time::timeout(stream_inactivity_timeout(), response.chunk()) => chunk,
+                        };
+                        match chunk {
                             Ok(Ok(Some(chunk))) => match byte_tx.try_send(chunk) {
                                 Ok(_) => {
                                     monitoring_reader.note_activity(&stream_for_reader);
@@ -204,8 +347,11 @@ async fn run_stream_task(
                 });
 
                 let tx_clone = tx.clone();
-                let recording_state_clone = recording_state.clone();
+                let recordings_clone = recordings.clone();
+                let taps_clone = taps.clone();
                 let nnnn_tx_clone = nnnn_tx.clone();
+                let attention_tx_clone = attention_tx.clone();
+                let levels_tx_clone = levels_tx.clone();
                 let stream_for_decode = stream_url.clone();
                 let decoding_task = tokio::task::spawn_blocking(move || {
                     let reader = ChannelReader {
@@ -219,9 +365,14 @@ async fn run_stream_task(
                         mss,
                         content_type,
                         &tx_clone,
-                        &recording_state_clone,
+                        &recordings_clone,
+                        &taps_clone,
                         &nnnn_tx_clone,
+                        &attention_tx_clone,
+                        &levels_tx_clone,
+                        &stop,
                         &stream_for_decode,
+                        preroll_secs,
                     )
                 });
                 if let Err(e) = decoding_task.await? {
@@ -248,13 +399,19 @@ async fn run_stream_task(
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 fn process_stream(
     mss: MediaSourceStream,
     content_type: Option<String>,
-    tx: &TokioSender<(String, String, String, String, Duration, String)>,
-    recording_state: &Arc<Mutex<Option<RecordingState>>>,
-    nnnn_tx: &BroadcastSender<()>,
+    tx: &TokioSender<(SameHeader, String)>,
+    recordings: &RecordingRegistry,
+    taps: &StreamTaps,
+    nnnn_tx: &BroadcastSender<EndOfMessage>,
+    attention_tx: &TokioSender<AttentionDetection>,
+    levels_tx: &TokioSender<LevelReading>,
+    stop: &watch::Receiver<()>,
     stream_label: &str,
+    preroll_secs: u64,
 ) -> Result<()> {
     let runtime = tokio::runtime::Handle::current();
 
@@ -264,186 +421,175 @@ fn process_stream(
             hint.with_extension("mp3");
         }
     }
-    let fmt_opts = FormatOptions {
-        enable_gapless: true,
-        ..Default::default()
-    };
-    let probed = symphonia::default::get_probe()
-        .format(&hint, mss, &fmt_opts, &MetadataOptions::default())
-        .context("Unsupported format")?;
-    let mut format = probed.format;
-
-    let track = format
-        .default_track()
-        .ok_or_else(|| anyhow!("No default track found"))?;
-    let mut track_id = track.id;
-    let mut decoder = symphonia::default::get_codecs()
-        .make(&track.codec_params, &DecoderOptions::default())
-        .context("Failed to make decoder")?;
 
     let mut same_receiver = SameReceiverBuilder::new(TARGET_SAMPLE_RATE).build();
-    let mut resampler: Option<SincFixedIn<f32>> = None;
-    let mut current_input_rate: Option<u32> = None;
-
-    const CHUNK_SIZE: usize = 2048;
-    let mut audio_buffer: Vec<f32> = Vec::new();
-
-    loop {
-        let packet = match format.next_packet() {
-            Ok(pkt) => pkt,
-            Err(SymphoniaError::ResetRequired) => {
-                if let Some(new_track) = format.default_track() {
-                    track_id = new_track.id;
-                    decoder = symphonia::default::get_codecs()
-                        .make(&new_track.codec_params, &DecoderOptions::default())
-                        .context("Failed to rebuild decoder after ResetRequired")?;
+    let mut attention = AttentionDetector::new(TARGET_SAMPLE_RATE);
+    let mut meter = LevelMeter::new(TARGET_SAMPLE_RATE);
+    let mut preroll = PreRollBuffer::new(preroll_secs);
+    // Stream position, in samples, where each active recording's audio begins.
+    let mut recording_starts: HashMap<u64, u64> = HashMap::new();
+    let mut open_header: Option<SameHeader> = None;
+    // Stream position after which the open header is considered over even
+    // without an NNNN, once it has purged or its recording has timed out.
+    let mut open_header_until: u64 = 0;
+    // Stream position where the bursts of the last decoded header ended.
+    let mut header_end: u64 = 0;
+    let mut last_burst_end: u64 = 0;
+
+    decode_media(
+        mss,
+        &hint,
+        stream_label,
+        TARGET_SAMPLE_RATE,
+        |samples_f32| {
+            // Audio still buffered from a connection that has been stopped
+            // must not start alerts.
+            if stop.has_changed().is_err() {
+                return Err(anyhow!("Stream monitor stopped"));
+            }
+            preroll.push(&samples_f32);
+            taps.publish(stream_label, &samples_f32);
+            let signals = attention.process(&samples_f32);
+            let position = attention.position();
+            if position >= open_header_until {
+                if let Some(header) = open_header.take() {
+                    warn!(
+                        stream = %stream_label,
+                        "No NNNN heard for {}; later attention signals are not credited to it",
+                        header
+                    );
                 }
-                current_input_rate = None;
-                resampler = None;
-                audio_buffer.clear();
-                continue;
             }
-            Err(SymphoniaError::IoError(_)) => break,
-            Err(e) => {
-                error!(stream = %stream_label, "Packet error: {}", e);
-                break;
+            if let Some(levels) = meter.process(&samples_f32) {
+                let reading = LevelReading {
+                    stream: stream_label.to_string(),
+                    levels,
+                };
+                if let Err(TrySendError::Closed(_)) = levels_tx.try_send(reading) {
+                    warn!(stream = %stream_label, "Level monitor channel closed unexpectedly.");
+                }
             }
-        };
-
-        if packet.track_id() != track_id {
-            continue;
-        }
 
-        match decoder.decode(&packet) {
-            Ok(decoded) => {
-                if decoded.frames() == 0 {
-                    continue;
+            let active = recordings.for_stream(stream_label);
+            recording_starts.retain(|id, _| active.iter().any(|state| state.id == *id));
+
+            for recording in &active {
+                let samples = match recording_starts.entry(recording.id) {
+                    Entry::Occupied(_) => samples_f32.clone(),
+                    Entry::Vacant(entry) => {
+                        // A recording that just started gets the buffered
+                        // pre-roll, which already ends with the current chunk.
+                        // The recording opens with a generated header, so the
+                        // bursts as received are cut from the front.
+                        let samples = if preroll.is_empty() {
+                            samples_f32.clone()
+                        } else {
+                            let mut snapshot = preroll.snapshot();
+                            let snapshot_start = position.saturating_sub(snapshot.len() as u64);
+                            let header_len = header_end.saturating_sub(snapshot_start) as usize;
+                            snapshot.drain(..header_len.min(snapshot.len()));
+                            snapshot
+                        };
+                        entry.insert(position.saturating_sub(samples.len() as u64));
+                        samples
+                    }
+                };
+                if let Err(TrySendError::Closed(_)) = recording.audio_tx.try_send(samples) {
+                    warn!(
+                        stream = %stream_label,
+                        "Recording task channel closed unexpectedly."
+                    );
                 }
-                let spec = *decoded.spec();
+            }
 
-                if current_input_rate != Some(spec.rate) {
-                    current_input_rate = Some(spec.rate);
-                    use rubato::{
-                        SincInterpolationParameters, SincInterpolationType, WindowFunction,
-                    };
-                    if current_input_rate.unwrap() == TARGET_SAMPLE_RATE {
-                        resampler = Some(
-                            SincFixedIn::new(
-                                TARGET_SAMPLE_RATE as f64 / spec.rate as f64,
-                                2.0,
-                                SincInterpolationParameters {
-                                    sinc_len: 256,
-                                    f_cutoff: 0.95,
-                                    interpolation: SincInterpolationType::Linear,
-                                    oversampling_factor: 256,
-                                    window: WindowFunction::BlackmanHarris2,
-                                },
-                                CHUNK_SIZE,
-                                1, // mono
-                            )
-                            .expect("failed to create resampler"),
-                        );
-                    } else {
-                        info!(
-                            stream = %stream_label,
-                            "Stream detected with sample rate {}. Resampling to {}.",
-                            spec.rate,
-                            TARGET_SAMPLE_RATE
-                        );
-                        resampler = Some(
-                            SincFixedIn::new(
-                                TARGET_SAMPLE_RATE as f64 / spec.rate as f64,
-                                2.0,
-                                SincInterpolationParameters {
-                                    sinc_len: 256,
-                                    f_cutoff: 0.95,
-                                    interpolation: SincInterpolationType::Linear,
-                                    oversampling_factor: 256,
-                                    window: WindowFunction::BlackmanHarris2,
-                                },
-                                CHUNK_SIZE,
-                                1, // mono
-                            )
-                            .expect("failed to create resampler"),
-                        );
-                    }
+            for signal in signals {
+                mark_recordings(&active, &recording_starts, &signal);
+                let ended_ago =
+                    (position - signal.start - signal.length) as f64 / TARGET_SAMPLE_RATE as f64;
+                let duration = signal.duration(TARGET_SAMPLE_RATE);
+                let detection = AttentionDetection {
+                    tone: signal.tone,
+                    stream: stream_label.to_string(),
+                    started_at: Utc::now()
+                        - chrono::Duration::milliseconds(
+                            ((ended_ago + duration.as_secs_f64()) * 1000.0) as i64,
+                        ),
+                    duration_secs: duration.as_secs_f64(),
+                    header: open_header.as_ref().map(SameHeader::to_string),
+                };
+                if let Err(e) = runtime.block_on(attention_tx.send(detection)) {
+                    error!(stream = %stream_label, "Failed to send attention signal: {}", e);
                 }
-                let rs = resampler
-                    .as_mut()
-                    .expect("resampler must be initialized when decoding begins");
-
-                let mut mono_samples = vec![0.0f32; decoded.frames()];
-                let mut sample_buf = SampleBuffer::<f32>::new(decoded.frames() as u64, spec);
-                sample_buf.copy_interleaved_ref(decoded);
-                for (i, frame) in sample_buf
-                    .samples()
-                    .chunks_exact(spec.channels.count())
-                    .enumerate()
-                {
-                    mono_samples[i] = frame.iter().sum::<f32>() / frame.len() as f32;
+            }
+
+            for event in same_receiver.iter_events(samples_f32) {
+                if let SameEventType::Link(LinkState::Burst(_)) = event.what() {
+                    last_burst_end = event.input_sample_counter();
+                    continue;
                 }
-                audio_buffer.extend_from_slice(&mono_samples);
-
-                while audio_buffer.len() >= CHUNK_SIZE {
-                    let chunk_to_process = audio_buffer[..CHUNK_SIZE].to_vec();
-                    let resampled = rs.process(&[chunk_to_process], None)?;
-                    let samples_f32 = resampled[0].clone();
-
-                    if let Some(audio_tx) = {
-                        let recorder = recording_state.blocking_lock();
-                        recorder
-                            .as_ref()
-                            .filter(|state| state.source_stream == stream_label)
-                            .map(|state| state.audio_tx.clone())
-                    } {
-                        if let Err(e) = audio_tx.try_send(samples_f32.clone()) {
-                            if let TrySendError::Closed(_) = e {
-                                warn!(
-                                    stream = %stream_label,
-                                    "Recording task channel closed unexpectedly."
-                                );
+                let Some(msg) = event.into_message_ok() else {
+                    continue;
+                };
+                match msg {
+                    SameMessage::StartOfMessage(header) => {
+                        let header = match SameHeader::parse(header.as_str()) {
+                            Ok(header) => header,
+                            Err(e) => {
+                                error!(stream = %stream_label, "Discarding malformed header '{}': {}", header.as_str(), e);
+                                continue;
                             }
+                        };
+                        let open_for = alerts::recording_timeout(header.purge_duration());
+                        open_header_until = attention.position()
+                            + (open_for.as_secs_f64() * TARGET_SAMPLE_RATE as f64) as u64;
+                        open_header = Some(header.clone());
+                        header_end = last_burst_end;
+                        if let Err(e) =
+                            runtime.block_on(tx.send((header, stream_label.to_string())))
+                        {
+                            error!(stream = %stream_label, "Failed to send decoded data: {}", e);
                         }
                     }
-
-                    for msg in same_receiver.iter_messages(samples_f32) {
-                        match msg {
-                            SameMessage::StartOfMessage(header) => {
-                                let event = header.event_str().to_string();
-                                let locations =
-                                    header.location_str_iter().collect::<Vec<_>>().join(", ");
-                                let originator = header.originator_str().to_string();
-                                let raw_header = header.as_str().to_string();
-                                let purge_time = header.valid_duration();
-                                let std_purge_time =
-                                    Duration::from_secs(purge_time.num_seconds().max(0) as u64);
-                                if let Err(e) = runtime.block_on(tx.send((
-                                    event,
-                                    locations,
-                                    originator,
-                                    raw_header,
-                                    std_purge_time,
-                                    stream_label.to_string(),
-                                ))) {
-                                    error!(stream = %stream_label, "Failed to send decoded data: {}", e);
-                                }
-                            }
-                            SameMessage::EndOfMessage => {
-                                info!(stream = %stream_label, "NNNN (End of Message) detected");
-                                if let Err(e) = nnnn_tx.send(()) {
-                                    error!(stream = %stream_label, "Failed to broadcast NNNN signal: {}", e);
-                                }
-                            }
+                    SameMessage::EndOfMessage => {
+                        info!(stream = %stream_label, "NNNN (End of Message) detected");
+                        let eom = EndOfMessage {
+                            stream_id: stream_label.to_string(),
+                            header: open_header.take(),
+                        };
+                        if let Err(e) = nnnn_tx.send(eom) {
+                            error!(stream = %stream_label, "Failed to broadcast NNNN signal: {}", e);
                         }
                     }
-                    audio_buffer.drain(..CHUNK_SIZE);
                 }
             }
-            Err(e) => {
-                warn!(stream = %stream_label, "Decode error: {}", e);
-            }
+            Ok(())
+        },
+    )
+}
+
+/// Marks `signal` in every recording that was running while it played.
+fn mark_recordings(
+    active: &[RecordingState],
+    recording_starts: &HashMap<u64, u64>,
+    signal: &AttentionSignal,
+) {
+    let signal_end = signal.start + signal.length;
+    for recording in active {
+        let Some(&start) = recording_starts.get(&recording.id) else {
+            continue;
+        };
+        if signal_end <= start {
+            continue;
         }
+        let from = signal.start.max(start);
+        recording.markers.lock().push(RecordingMarker {
+            position: from - start,
+            length: signal_end - from,
+            label: format!(
+                "{} ({:.1} s)",
+                signal.tone.label(),
+                signal.duration(TARGET_SAMPLE_RATE).as_secs_f32()
+            ),
+        });
     }
-
-    Ok(())
 }
@@ -1,15 +1,26 @@
-use crate::monitoring::{LogEntry, MonitoringEvent, MonitoringHub, StreamStatusPayload};
+use crate::attention::AttentionDetection;
+use crate::config::ConfigHandle;
+use crate::eas_text;
+use crate::filter::{self, AlertDecision};
+use crate::header::SameHeader;
+use crate::history::{AlertHistory, HistoryPage, HistoryQuery, HistoryRecord};
+use crate::monitoring::{
+    LogEntry, MonitoringEvent, MonitoringHub, PendingRelayPayload, RelayQueuePayload,
+    StreamStatusPayload,
+};
+use crate::relay_hold::RelayHolds;
 use crate::state::{ActiveAlert, AppState};
 use crate::Config;
 use anyhow::Result;
 use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
-use axum::extract::{Query, Request, State};
+use axum::extract::{Path, Query, Request, State};
 use axum::middleware;
 use axum::middleware::Next;
 use axum::response::{IntoResponse, Response};
-use axum::routing::get;
+use axum::routing::{get, post};
 use axum::{Json, Router};
 use base64::Engine;
+use chrono::{DateTime, Utc};
 use reqwest::header;
 use reqwest::header::HeaderValue;
 use reqwest::header::{AUTHORIZATION, CONTENT_TYPE};
@@ -21,13 +32,16 @@ use std::sync::Arc;
 use tokio::net::TcpListener;
 use tokio::sync::Mutex;
 use tokio::time::{self, Duration, MissedTickBehavior};
-use tower_http::cors::CorsLayer;
+use tower_http::cors::{AllowOrigin, CorsLayer};
 use tracing::{error, info};
 
 #[derive(Clone)]
 struct ApiState {
+    config: ConfigHandle,
     app_state: Arc<Mutex<AppState>>,
     monitoring: MonitoringHub,
+    history: AlertHistory,
+    holds: RelayHolds,
 }
 
 #[derive(Debug, Deserialize, Default)]
@@ -49,6 +63,42 @@ struct HealthResponse {
 struct StatusResponse {
     streams: Vec<StreamStatusPayload>,
     active_alerts: Vec<ActiveAlert>,
+    relay_queues: Vec<RelayQueuePayload>,
+    pending_relays: Vec<PendingRelayPayload>,
+    attention_events: Vec<AttentionDetection>,
+}
+
+#[derive(Debug, Serialize)]
+struct ConfigReloadResponse {
+    version: u64,
+    error: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct FilterTestRequest {
+    header: String,
+    stream: Option<String>,
+    received_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize)]
+struct FilterTestResponse {
+    header: String,
+    event_text: String,
+    locations: String,
+    stream: Option<String>,
+    decision: AlertDecision,
+}
+
+#[derive(Debug, Serialize)]
+struct RelayDecisionResponse {
+    id: u64,
+    action: String,
+}
+
+#[derive(Debug, Serialize)]
+struct ErrorResponse {
+    error: String,
 }
 
 #[derive(Debug, Deserialize)]
@@ -63,12 +113,18 @@ enum WsMessage {
     Log(LogEntry),
     Stream(StreamStatusPayload),
     Alerts(Vec<ActiveAlert>),
+    RelayQueue(RelayQueuePayload),
+    PendingRelays(Vec<PendingRelayPayload>),
+    Attention(AttentionDetection),
 }
 
 #[derive(Debug, Serialize)]
 struct SnapshotPayload {
     streams: Vec<StreamStatusPayload>,
     active_alerts: Vec<ActiveAlert>,
+    relay_queues: Vec<RelayQueuePayload>,
+    pending_relays: Vec<PendingRelayPayload>,
+    attention_events: Vec<AttentionDetection>,
     logs: Vec<LogEntry>,
 }
 
@@ -78,58 +134,50 @@ impl From<MonitoringEvent> for WsMessage {
             MonitoringEvent::Log(entry) => WsMessage::Log(entry),
             MonitoringEvent::Stream(status) => WsMessage::Stream(status),
             MonitoringEvent::Alerts(alerts) => WsMessage::Alerts(alerts),
+            MonitoringEvent::RelayQueue(queue) => WsMessage::RelayQueue(queue),
+            MonitoringEvent::PendingRelays(pending) => WsMessage::PendingRelays(pending),
+            MonitoringEvent::Attention(detection) => WsMessage::Attention(detection),
         }
     }
 }
 
-fn cors_layer() -> CorsLayer {
-    let json_config = Config::from_config_json("/app/config.json");
-
-    if json_config.as_ref().unwrap().use_reverse_proxy.to_string() != "true" {
-        let origin: HeaderValue = format!(
-            "http://{}:{}/",
-            json_config.as_ref().unwrap().monitoring_bind_host,
-            json_config.as_ref().unwrap().monitoring_bind_port
+/// The origin the dashboard is served from.
+fn dashboard_origin(json_config: &Config) -> String {
+    if !json_config.use_reverse_proxy {
+        format!(
+            "http://{}:{}",
+            json_config.monitoring_bind_host, json_config.monitoring_bind_port
         )
-        .parse()
-        .unwrap_or_else(|_| HeaderValue::from_static("http://localhost:8080"));
-
-        CorsLayer::new()
-            .allow_origin(origin)
-            .allow_methods([
-                Method::GET,
-                Method::POST,
-                Method::PUT,
-                Method::PATCH,
-                Method::DELETE,
-                Method::OPTIONS,
-            ])
-            .allow_headers([AUTHORIZATION, CONTENT_TYPE])
-            .max_age(Duration::from_secs(86400))
     } else {
-        let origin: HeaderValue = format!(
-            "http://{}/",
-            json_config.as_ref().unwrap().ws_reverse_proxy_url
-        )
-        .parse()
-        .unwrap_or_else(|_| HeaderValue::from_static("http://localhost"));
-
-        CorsLayer::new()
-            .allow_origin(origin)
-            .allow_methods([
-                Method::GET,
-                Method::POST,
-                Method::PUT,
-                Method::PATCH,
-                Method::DELETE,
-                Method::OPTIONS,
-            ])
-            .allow_headers([AUTHORIZATION, CONTENT_TYPE])
-            .max_age(Duration::from_secs(86400))
+        format!("http://{}", json_config.ws_reverse_proxy_url)
     }
 }
 
-async fn auth(req: Request, next: Next) -> Result<Response, StatusCode> {
+/// Allows the dashboard's origin as set in the running config, so a reload
+/// applies without restarting the server.
+fn cors_layer(config: ConfigHandle) -> CorsLayer {
+    CorsLayer::new()
+        .allow_origin(AllowOrigin::predicate(move |origin: &HeaderValue, _| {
+            let allowed = dashboard_origin(&config.current());
+            origin.as_bytes() == allowed.trim_end_matches('/').as_bytes()
+        }))
+        .allow_methods([
+            Method::GET,
+            Method::POST,
+            Method::PUT,
+            Method::PATCH,
+            Method::DELETE,
+            Method::OPTIONS,
+        ])
+        .allow_headers([AUTHORIZATION, CONTENT_TYPE])
+        .max_age(Duration::from_secs(86400))
+}
+
+async fn auth(
+    State(state): State<ApiState>,
+    req: Request,
+    next: Next,
+) -> Result<Response, StatusCode> {
     if req.method() == Method::OPTIONS {
         return Ok(next.run(req).await);
     }
@@ -140,22 +188,22 @@ async fn auth(req: Request, next: Next) -> Result<Response, StatusCode> {
         .and_then(|header| header.to_str().ok());
 
     match auth_header {
-        Some(auth_header) if token_is_valid(auth_header) => Ok(next.run(req).await),
+        Some(auth_header) if token_is_valid(&state.config.current(), auth_header) => {
+            Ok(next.run(req).await)
+        }
         _ => Err(StatusCode::UNAUTHORIZED),
     }
 }
 
-fn token_is_valid(auth_header: &str) -> bool {
-    let json_config = Config::from_config_json("/app/config.json");
-
+fn token_is_valid(json_config: &Config, auth_header: &str) -> bool {
     if !auth_header.starts_with("Bearer ") {
         info!("Auth header does not start with 'Bearer '");
         return false;
     }
 
     let token = &auth_header[7..];
-    let username = json_config.as_ref().unwrap().dashboard_username.clone();
-    let password = json_config.as_ref().unwrap().dashboard_password.clone();
+    let username = json_config.dashboard_username.clone();
+    let password = json_config.dashboard_password.clone();
 
     if username.is_empty() || password.is_empty() || username == "admin" || password == "password" {
         info!("Default or empty username/password in use, rejecting token");
@@ -172,25 +220,37 @@ fn token_is_valid(auth_header: &str) -> bool {
 
 pub async fn run_server(
     bind_addr: SocketAddr,
+    config: ConfigHandle,
     app_state: Arc<Mutex<AppState>>,
     monitoring: MonitoringHub,
+    history: AlertHistory,
+    holds: RelayHolds,
 ) -> Result<()> {
     let state = ApiState {
+        config: config.clone(),
         app_state,
         monitoring,
+        history,
+        holds,
     };
 
     let protected_router = Router::new()
         .route("/api/logs", get(logs_handler))
         .route("/api/status", get(status_handler))
-        .layer(cors_layer())
+        .route("/api/history", get(history_handler))
+        .route("/api/history/:id", get(history_entry_handler))
+        .route("/api/config/reload", post(config_reload_handler))
+        .route("/api/filters/test", post(filter_test_handler))
+        .route("/api/relays/:id/approve", post(relay_approve_handler))
+        .route("/api/relays/:id/cancel", post(relay_cancel_handler))
+        .layer(cors_layer(config.clone()))
         .with_state(state.clone())
-        .route_layer(middleware::from_fn(auth));
+        .route_layer(middleware::from_fn_with_state(state.clone(), auth));
 
     let router = Router::new()
         .route("/api/health", get(health_handler))
         .route("/ws", get(ws_handler))
-        .layer(cors_layer())
+        .layer(cors_layer(config.clone()))
         .merge(protected_router)
         .with_state(state.clone());
 
@@ -225,9 +285,148 @@ async fn status_handler(State(state): State<ApiState>) -> Json<StatusResponse> {
     Json(StatusResponse {
         streams,
         active_alerts,
+        relay_queues: state.monitoring.relay_queue_snapshots(),
+        pending_relays: state.monitoring.pending_relays(),
+        attention_events: state.monitoring.attention_events(),
     })
 }
 
+async fn history_handler(
+    Query(query): Query<HistoryQuery>,
+    State(state): State<ApiState>,
+) -> Result<Json<HistoryPage>, StatusCode> {
+    state.history.search(query).await.map(Json).map_err(|err| {
+        error!("Failed to search alert history: {err}");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })
+}
+
+async fn history_entry_handler(
+    Path(id): Path<i64>,
+    State(state): State<ApiState>,
+) -> Result<Json<HistoryRecord>, StatusCode> {
+    match state.history.get(id).await {
+        Ok(Some(record)) => Ok(Json(record)),
+        Ok(None) => Err(StatusCode::NOT_FOUND),
+        Err(err) => {
+            error!("Failed to load alert history entry {id}: {err}");
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+async fn config_reload_handler(
+    State(state): State<ApiState>,
+) -> (StatusCode, Json<ConfigReloadResponse>) {
+    let config = state.config.clone();
+    let result = tokio::task::spawn_blocking(move || config.reload("API request"))
+        .await
+        .unwrap_or_else(|err| Err(err.into()));
+    match result {
+        Ok(version) => (
+            StatusCode::OK,
+            Json(ConfigReloadResponse {
+                version,
+                error: None,
+            }),
+        ),
+        Err(err) => (
+            StatusCode::UNPROCESSABLE_ENTITY,
+            Json(ConfigReloadResponse {
+                version: state.config.version(),
+                error: Some(format!("{:#}", err)),
+            }),
+        ),
+    }
+}
+
+async fn relay_approve_handler(
+    Path(id): Path<u64>,
+    State(state): State<ApiState>,
+) -> Result<Json<RelayDecisionResponse>, (StatusCode, Json<ErrorResponse>)> {
+    decide_held_relay(&state, id, true)
+}
+
+async fn relay_cancel_handler(
+    Path(id): Path<u64>,
+    State(state): State<ApiState>,
+) -> Result<Json<RelayDecisionResponse>, (StatusCode, Json<ErrorResponse>)> {
+    decide_held_relay(&state, id, false)
+}
+
+fn decide_held_relay(
+    state: &ApiState,
+    id: u64,
+    approve: bool,
+) -> Result<Json<RelayDecisionResponse>, (StatusCode, Json<ErrorResponse>)> {
+    let action = if approve { "approved" } else { "cancelled" };
+    if !state.holds.decide(id, approve) {
+        return Err((
+            StatusCode::NOT_FOUND,
+            Json(ErrorResponse {
+                error: format!("no held relay with id {}", id),
+            }),
+        ));
+    }
+    info!(id, "Held relay {} via API", action);
+    Ok(Json(RelayDecisionResponse {
+        id,
+        action: action.to_string(),
+    }))
+}
+
+/// Runs a header through the same checks a received alert goes through and
+/// reports what each output would do, without recording or sending anything.
+async fn filter_test_handler(
+    State(state): State<ApiState>,
+    Json(request): Json<FilterTestRequest>,
+) -> Result<Json<FilterTestResponse>, (StatusCode, Json<ErrorResponse>)> {
+    let header = SameHeader::parse(&request.header).map_err(|err| {
+        (
+            StatusCode::BAD_REQUEST,
+            Json(ErrorResponse {
+                error: format!("invalid header: {}", err),
+            }),
+        )
+    })?;
+    let config = state.config.current();
+
+    let stream = request.stream.map(|stream| {
+        stream
+            .trim()
+            .trim_start_matches('#')
+            .parse::<usize>()
+            .ok()
+            .and_then(|number| config.icecast_stream_urls.get(number.wrapping_sub(1)))
+            .cloned()
+            .unwrap_or(stream)
+    });
+
+    let alert_data = eas_text::translate(&header, config.timezone);
+    let mut alert = ActiveAlert::new(
+        alert_data.clone(),
+        header.clone(),
+        header.purge_duration(),
+        stream.clone().unwrap_or_default(),
+    );
+    if stream.is_none() {
+        alert.sources.clear();
+    }
+    if let Some(received_at) = request.received_at {
+        alert.received_at = received_at;
+    }
+
+    alert.decision = filter::decide(&config, &alert);
+
+    Ok(Json(FilterTestResponse {
+        header: header.to_string(),
+        event_text: alert_data.event_text,
+        locations: alert_data.locations,
+        stream,
+        decision: alert.decision,
+    }))
+}
+
 async fn ws_handler(
     ws: WebSocketUpgrade,
     State(state): State<ApiState>,
@@ -235,7 +434,7 @@ async fn ws_handler(
 ) -> impl IntoResponse {
     let auth_header = format!("Bearer {}", params.auth);
 
-    if !token_is_valid(&auth_header) {
+    if !token_is_valid(&state.config.current(), &auth_header) {
         (StatusCode::UNAUTHORIZED, "Unauthorized").into_response()
     } else {
         ws.on_upgrade(move |socket| ws_connection(socket, state))
@@ -304,6 +503,9 @@ async fn send_snapshot(socket: &mut WebSocket, state: &ApiState) -> Result<()> {
     let snapshot = WsMessage::Snapshot(SnapshotPayload {
         streams,
         active_alerts,
+        relay_queues: state.monitoring.relay_queue_snapshots(),
+        pending_relays: state.monitoring.pending_relays(),
+        attention_events: state.monitoring.attention_events(),
         logs,
     });
     send_ws_message(socket, &snapshot).await
@@ -1,16 +1,17 @@
-use crate::config::Config;
+use crate::config::ConfigHandle;
 use anyhow::Result;
 use chrono::{Duration, Utc};
 use tokio::time::interval;
 use tracing::{info, warn};
 
-pub async fn run_log_cleanup(config: Config) -> Result<()> {
+pub async fn run_log_cleanup(config_handle: ConfigHandle) -> Result<()> {
     info!("Log cleanup task started. Will run every 24 hours.");
     let mut timer = interval(std::time::Duration::from_secs(24 * 60 * 60));
 
     loop {
         timer.tick().await;
         info!("Running daily log cleanup...");
+        let config = config_handle.current();
 
         let retention_period = Duration::days(3);
         let now = Utc::now().date_naive();
@@ -31,7 +32,7 @@ pub async fn run_log_cleanup(config: Config) -> Result<()> {
 
             if let Some(filename_str) = path.file_name().and_then(|s| s.to_str()) {
                 if filename_str.starts_with(&config.alert_log_file) {
-                    if let Some(date_part) = filename_str.split('.').last() {
+                    if let Some(date_part) = filename_str.split('.').next_back() {
                         if let Ok(file_date) =
                             chrono::NaiveDate::parse_from_str(date_part, "%Y-%m-%d")
                         {
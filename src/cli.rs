@@ -0,0 +1,146 @@
+use crate::decode;
+use crate::encode::{self, EncodeOptions};
+use crate::header::{AttentionTone, SameHeader};
+use crate::media;
+use anyhow::{bail, Context, Result};
+use sameold::Message as SameMessage;
+use serde_json::json;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+
+const DECODE_USAGE: &str = "Usage: asmara_rust decode <file> [file...]";
+const ENCODE_USAGE: &str = "Usage: asmara_rust encode <header> <output.wav> \
+[--attention eas|nwr|none] [--attention-secs N] [--voice FILE] \
+[--gap-ms N] [--amplitude 0.0-1.0] [--sample-rate HZ]";
+
+/// Handles offline subcommands. Returns `Ok(false)` when the arguments do not
+/// name a subcommand and the listener should start as usual.
+pub fn run(args: &[String]) -> Result<bool> {
+    match args.first().map(String::as_str) {
+        Some("decode") => {
+            decode(&args[1..])?;
+            Ok(true)
+        }
+        Some("encode") => {
+            encode(&args[1..])?;
+            Ok(true)
+        }
+        _ => Ok(false),
+    }
+}
+
+fn decode(args: &[String]) -> Result<()> {
+    if args.is_empty() || args.iter().any(|arg| arg == "-h" || arg == "--help") {
+        bail!(DECODE_USAGE);
+    }
+
+    // One unreadable file is reported on its own line and does not stop the
+    // rest of the run.
+    let mut failed = 0;
+    for path in args.iter().map(PathBuf::from) {
+        let file = path.display().to_string();
+        let result = decode::decode_file(&path, |msg, sample_offset| {
+            let seconds = sample_offset as f64 / media::TARGET_SAMPLE_RATE as f64;
+            let line = match msg {
+                SameMessage::StartOfMessage(header) => {
+                    let parsed = SameHeader::parse(header.as_str());
+                    json!({
+                        "file": file,
+                        "type": "StartOfMessage",
+                        "sample_offset_48k": sample_offset,
+                        "seconds": seconds,
+                        "header": header.as_str(),
+                        "valid": parsed.is_ok(),
+                        "error": parsed.err().map(|e| e.to_string()),
+                    })
+                }
+                SameMessage::EndOfMessage => json!({
+                    "file": file,
+                    "type": "EndOfMessage",
+                    "sample_offset_48k": sample_offset,
+                    "seconds": seconds,
+                }),
+            };
+            println!("{}", line);
+        });
+        if let Err(e) = result {
+            failed += 1;
+            let line = json!({
+                "file": file,
+                "type": "Error",
+                "error": format!("{:#}", e),
+            });
+            println!("{}", line);
+        }
+    }
+
+    if failed > 0 {
+        bail!("{} of {} file(s) could not be decoded", failed, args.len());
+    }
+    Ok(())
+}
+
+fn encode(args: &[String]) -> Result<()> {
+    if args.iter().any(|arg| arg == "-h" || arg == "--help") {
+        bail!(ENCODE_USAGE);
+    }
+
+    let mut positional = Vec::new();
+    let mut options = EncodeOptions::default();
+    let (mut attention, mut attention_duration) = options.attention_tone.unzip();
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if !arg.starts_with("--") {
+            positional.push(arg.as_str());
+            continue;
+        }
+        let value = iter
+            .next()
+            .with_context(|| format!("Missing value for {}\n{}", arg, ENCODE_USAGE))?;
+        match arg.as_str() {
+            "--attention" => {
+                attention = if value.eq_ignore_ascii_case("none") {
+                    None
+                } else {
+                    Some(AttentionTone::from_str(value)?)
+                };
+            }
+            "--attention-secs" => {
+                let secs = parse_value::<f64>(arg, value)?;
+                attention_duration = Some(
+                    Duration::try_from_secs_f64(secs)
+                        .with_context(|| format!("Invalid value '{}' for {}", value, arg))?,
+                );
+            }
+            "--voice" => options.voice = Some(PathBuf::from(value)),
+            "--gap-ms" => {
+                options.burst_gap = Duration::from_millis(parse_value::<u64>(arg, value)?)
+            }
+            "--amplitude" => options.amplitude = parse_value(arg, value)?,
+            "--sample-rate" => options.sample_rate = parse_value(arg, value)?,
+            _ => bail!("Unknown option {}\n{}", arg, ENCODE_USAGE),
+        }
+    }
+
+    let [header, output] = positional[..] else {
+        bail!(ENCODE_USAGE);
+    };
+    let header =
+        SameHeader::parse(header).with_context(|| format!("Invalid SAME header '{}'", header))?;
+
+    options.attention_tone = attention.zip(attention_duration);
+
+    encode::write_same_message(&PathBuf::from(output), &header, &options)?;
+    println!("Wrote {}", output);
+
+    Ok(())
+}
+
+fn parse_value<T: FromStr>(name: &str, value: &str) -> Result<T> {
+    value
+        .parse()
+        .ok()
+        .with_context(|| format!("Invalid value '{}' for {}", value, name))
+}
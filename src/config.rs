@@ -1,19 +1,28 @@
-use crate::filter::{self, FilterRule};
+use crate::filter::{self, FilterAction, FilterRule};
+use crate::header;
+use crate::levels::{self, LevelAlarmConfig};
+use crate::relay::{self, RelayDestination};
 use anyhow::{anyhow, Context, Result};
 use chrono_tz::Tz;
 use std::collections::HashSet;
 use std::net::SocketAddr;
 use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::watch;
+use tokio::time::{interval, MissedTickBehavior};
+use tracing::{error, info, warn};
+
+const CONFIG_POLL_INTERVAL: Duration = Duration::from_secs(2);
 
 #[derive(Debug, Clone)]
 #[allow(dead_code)]
 pub struct Config {
     pub apprise_config_path: String,
-    pub icecast_relay: String,
     pub dasdec_url: String,
     pub should_relay_dasdec: bool,
-    pub icecast_intro: PathBuf,
-    pub icecast_outro: PathBuf,
+    pub relay_destinations: Vec<RelayDestination>,
     pub should_relay: bool,
     pub icecast_stream_urls: Vec<String>,
     pub shared_state_dir: PathBuf,
@@ -21,10 +30,14 @@ pub struct Config {
     pub dedicated_alert_log_file: PathBuf,
     pub timezone: Tz,
     pub watched_fips: HashSet<String>,
+    pub alert_dedup_window_secs: u64,
     pub recording_dir: PathBuf,
+    pub recording_preroll_secs: u64,
+    pub recording_postroll_secs: u64,
     pub monitoring_bind_addr: SocketAddr,
     pub monitoring_max_log_entries: usize,
     pub monitoring_activity_window_secs: u64,
+    pub level_alarms: LevelAlarmConfig,
     pub monitoring_bind_host: String,
     pub use_reverse_proxy: bool,
     pub monitoring_bind_port: u16,
@@ -34,7 +47,9 @@ pub struct Config {
     pub eas_relay_name: String,
     pub reverse_proxy_url: String,
     pub web_server_port: String,
+    pub enable_filters: bool,
     pub filters: Vec<FilterRule>,
+    pub filter_default_action: FilterAction,
     pub log_level: String,
 }
 
@@ -44,7 +59,11 @@ impl Config {
             .with_context(|| format!("Failed to read config file: {}", config_file))?;
         let config_json: serde_json::Value = serde_json::from_str(&config_data)
             .with_context(|| format!("Failed to parse config file: {}", config_file))?;
+        Self::from_value(&config_json)
+    }
 
+    /// Builds the config from the parsed contents of `config.json`.
+    pub fn from_value(config_json: &serde_json::Value) -> Result<Self> {
         let shared_dir: PathBuf = config_json
             .get("SHARED_STATE_DIR")
             .and_then(|v| v.as_str())
@@ -61,15 +80,6 @@ impl Config {
             .and_then(|v| v.as_bool())
             .ok_or_else(|| anyhow!("SHOULD_RELAY must be either true or false in config file"))?;
 
-        let icecast_relay = config_json
-            .get("ICECAST_RELAY")
-            .and_then(|v| v.as_str())
-            .unwrap_or("")
-            .to_string();
-
-        let mut icecast_intro: PathBuf = PathBuf::new();
-        let mut icecast_outro: PathBuf = PathBuf::new();
-
         let should_relay_dasdec = config_json
             .get("SHOULD_RELAY_DASDEC")
             .and_then(|v| v.as_bool())
@@ -83,24 +93,6 @@ impl Config {
             .unwrap_or("")
             .to_string();
 
-        if should_relay {
-            if icecast_relay.is_empty() {
-                return Err(anyhow!("ICECAST_RELAY must be set if SHOULD_RELAY is true"));
-            }
-
-            icecast_intro = config_json
-                .get("ICECAST_INTRO")
-                .and_then(|v| v.as_str())
-                .map(PathBuf::from)
-                .unwrap_or_else(|| "".into());
-
-            icecast_outro = config_json
-                .get("ICECAST_OUTRO")
-                .and_then(|v| v.as_str())
-                .map(PathBuf::from)
-                .unwrap_or_else(|| "".into());
-        }
-
         let tz_str = config_json
             .get("TZ")
             .and_then(|v| v.as_str())
@@ -116,6 +108,11 @@ impl Config {
             .map(str::to_string)
             .collect();
 
+        let alert_dedup_window_secs = config_json
+            .get("ALERT_DEDUP_WINDOW_SECS")
+            .and_then(|v| v.as_u64())
+            .unwrap_or(600);
+
         let recording_dir = shared_dir.join(
             config_json
                 .get("RECORDING_DIR")
@@ -123,6 +120,16 @@ impl Config {
                 .unwrap_or("recordings"),
         );
 
+        let recording_preroll_secs = config_json
+            .get("RECORDING_PREROLL_SECS")
+            .and_then(|v| v.as_u64())
+            .unwrap_or(10);
+
+        let recording_postroll_secs = config_json
+            .get("RECORDING_POSTROLL_SECS")
+            .and_then(|v| v.as_u64())
+            .unwrap_or(2);
+
         let icecast_stream_urls: Vec<String> = config_json
             .get("ICECAST_STREAM_URL_ARRAY")
             .and_then(|v| v.as_array())
@@ -155,6 +162,8 @@ impl Config {
             .unwrap_or(45)
             .max(1);
 
+        let level_alarms = levels::parse_alarm_config(config_json);
+
         let monitoring_bind_host: String = config_json
             .get("MONITORING_BIND_HOST")
             .and_then(|v| v.as_str())
@@ -227,14 +236,37 @@ impl Config {
             .unwrap_or("INFO")
             .to_string();
 
-        let filters = filter::parse_filters(&config_json);
+        let enable_filters = config_json
+            .get("ENABLE_FILTERS")
+            .and_then(|v| v.as_bool())
+            .unwrap_or(true);
+        let filters = filter::parse_filters(config_json, timezone)?;
+        let filter_default_action = filter::parse_default_action(config_json)?;
+
+        let relay_destinations = if should_relay {
+            relay::parse_destinations(config_json, &icecast_stream_urls, timezone)?
+        } else {
+            Vec::new()
+        };
+        if relay_destinations.iter().any(|dest| dest.rewrite.is_some()) {
+            let station_id = header::station_id_from_name(&eas_relay_name);
+            if station_id.trim_end() != eas_relay_name.trim() {
+                warn!(
+                    "EAS_RELAY_NAME '{}' is not a valid SAME station ID (up to 8 characters, no '+'); rewritten headers will carry '{}'",
+                    eas_relay_name, station_id
+                );
+            }
+        }
+        if should_relay && relay_destinations.is_empty() {
+            return Err(anyhow!(
+                "ICECAST_RELAY or ICECAST_RELAY_DESTINATIONS must be set if SHOULD_RELAY is true"
+            ));
+        }
 
         Ok(Self {
             icecast_stream_urls,
             apprise_config_path,
-            icecast_relay,
-            icecast_intro,
-            icecast_outro,
+            relay_destinations,
             should_relay,
             should_relay_dasdec,
             dasdec_url,
@@ -243,10 +275,14 @@ impl Config {
             dedicated_alert_log_file: shared_dir.join(log_filename),
             timezone,
             watched_fips,
+            alert_dedup_window_secs,
             recording_dir,
+            recording_preroll_secs,
+            recording_postroll_secs,
             monitoring_bind_addr,
             monitoring_max_log_entries,
             monitoring_activity_window_secs,
+            level_alarms,
             monitoring_bind_host,
             use_reverse_proxy,
             monitoring_bind_port,
@@ -256,8 +292,134 @@ impl Config {
             eas_relay_name,
             reverse_proxy_url,
             web_server_port,
+            enable_filters,
             filters,
+            filter_default_action,
             log_level,
         })
     }
 }
+
+/// A loaded configuration together with the reload generation it came from.
+#[derive(Debug, Clone)]
+pub struct ConfigSnapshot {
+    pub version: u64,
+    pub config: Arc<Config>,
+}
+
+/// Shared handle to the running configuration. Readers take cheap snapshots;
+/// `reload` only swaps in a new config when the file parses, so a broken edit
+/// leaves the running one in place.
+#[derive(Clone)]
+pub struct ConfigHandle {
+    path: Arc<PathBuf>,
+    tx: Arc<watch::Sender<ConfigSnapshot>>,
+    reload_lock: Arc<parking_lot::Mutex<()>>,
+}
+
+impl ConfigHandle {
+    pub fn load(config_file: &str) -> Result<Self> {
+        let config = Config::from_config_json(config_file)?;
+        let (tx, _rx) = watch::channel(ConfigSnapshot {
+            version: 1,
+            config: Arc::new(config),
+        });
+        Ok(Self {
+            path: Arc::new(PathBuf::from(config_file)),
+            tx: Arc::new(tx),
+            reload_lock: Arc::new(parking_lot::Mutex::new(())),
+        })
+    }
+
+    pub fn current(&self) -> Arc<Config> {
+        self.tx.borrow().config.clone()
+    }
+
+    pub fn version(&self) -> u64 {
+        self.tx.borrow().version
+    }
+
+    pub fn subscribe(&self) -> watch::Receiver<ConfigSnapshot> {
+        self.tx.subscribe()
+    }
+
+    /// Re-reads the config file and publishes it as a new version.
+    pub fn reload(&self, trigger: &str) -> Result<u64> {
+        let _guard = self.reload_lock.lock();
+        let path = self.path.to_string_lossy();
+        let config = match Config::from_config_json(&path) {
+            Ok(config) => config,
+            Err(e) => {
+                error!(
+                    "Rejected config reload ({}); keeping version {}: {:#}",
+                    trigger,
+                    self.version(),
+                    e
+                );
+                return Err(e);
+            }
+        };
+
+        let previous = self.current();
+        if previous.monitoring_bind_addr != config.monitoring_bind_addr
+            || previous.shared_state_dir != config.shared_state_dir
+            || previous.alert_log_file != config.alert_log_file
+            || previous.log_level != config.log_level
+        {
+            warn!(
+                "Bind address, state directory and log settings only take effect after a restart"
+            );
+        }
+
+        let version = self.version() + 1;
+        self.tx.send_replace(ConfigSnapshot {
+            version,
+            config: Arc::new(config),
+        });
+        info!(
+            "Configuration reloaded ({}); now at version {}",
+            trigger, version
+        );
+        Ok(version)
+    }
+}
+
+/// Reloads the config when its file changes on disk or the process gets SIGHUP.
+pub async fn run_config_watcher(handle: ConfigHandle) -> Result<()> {
+    let mut hangup = signal(SignalKind::hangup()).context("install SIGHUP handler")?;
+    let mut timer = interval(CONFIG_POLL_INTERVAL);
+    timer.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    let mut last_modified = modified_time(&handle.path).await;
+
+    loop {
+        tokio::select! {
+            _ = hangup.recv() => {
+                last_modified = modified_time(&handle.path).await;
+                reload_blocking(&handle, "SIGHUP").await;
+            }
+            _ = timer.tick() => {
+                let modified = modified_time(&handle.path).await;
+                if modified.is_some() && modified != last_modified {
+                    last_modified = modified;
+                    reload_blocking(&handle, "file change").await;
+                }
+            }
+        }
+    }
+}
+
+/// `reload` reads and parses the file with blocking I/O, so it runs off the
+/// async workers. Rejected configs are already logged by `reload`.
+async fn reload_blocking(handle: &ConfigHandle, trigger: &'static str) {
+    let handle = handle.clone();
+    if let Err(e) = tokio::task::spawn_blocking(move || handle.reload(trigger)).await {
+        error!("Config reload task failed: {}", e);
+    }
+}
+
+async fn modified_time(path: &PathBuf) -> Option<SystemTime> {
+    tokio::fs::metadata(path)
+        .await
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
@@ -0,0 +1,94 @@
+//! Offline SAME decoding of local audio files.
+
+use crate::media::{decode_media, TARGET_SAMPLE_RATE};
+use anyhow::{Context, Result};
+use sameold::{Message as SameMessage, SameReceiverBuilder};
+use std::fs::File;
+use std::path::Path;
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::probe::Hint;
+
+/// Decodes every SAME message in a local audio file. Offsets passed to
+/// `on_message` are where sameold produced the message, in samples at
+/// [`TARGET_SAMPLE_RATE`] from the start of the file.
+pub fn decode_file<F>(path: &Path, mut on_message: F) -> Result<()>
+where
+    F: FnMut(SameMessage, u64),
+{
+    let file = File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let mss = MediaSourceStream::new(Box::new(file), Default::default());
+
+    let mut hint = Hint::new();
+    if let Some(extension) = path.extension().and_then(|ext| ext.to_str()) {
+        hint.with_extension(extension);
+    }
+
+    let label = path.display().to_string();
+    let mut same_receiver = SameReceiverBuilder::new(TARGET_SAMPLE_RATE).build();
+    let mut file_len: u64 = 0;
+
+    decode_media(mss, &hint, &label, TARGET_SAMPLE_RATE, |samples_f32| {
+        file_len += samples_f32.len() as u64;
+        for event in same_receiver.iter_events(samples_f32) {
+            let offset = event.input_sample_counter();
+            if let Some(msg) = event.into_message_ok() {
+                on_message(msg, offset);
+            }
+        }
+        Ok(())
+    })?;
+
+    // Files usually end right after the last burst, so pad with silence to let
+    // the receiver finish framing anything still in its buffers. Whatever it
+    // yields belongs to the end of the file, not to the padding.
+    let padding = vec![0.0f32; TARGET_SAMPLE_RATE as usize];
+    for event in same_receiver.iter_events(padding) {
+        let offset = event.input_sample_counter().min(file_len);
+        if let Some(msg) = event.into_message_ok() {
+            on_message(msg, offset);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encode::{write_same_message, EncodeOptions};
+    use crate::header::SameHeader;
+
+    #[test]
+    fn finds_messages_in_an_encoded_file() {
+        let header = SameHeader::parse("ZCZC-EAS-RWT-012057+0100-0241500-WABC-FM-").unwrap();
+        let options = EncodeOptions {
+            sample_rate: 22050,
+            ..EncodeOptions::default()
+        };
+        let path = std::env::temp_dir().join(format!("asmara_decode_{}.wav", std::process::id()));
+        write_same_message(&path, &header, &options).unwrap();
+        let file_secs = hound::WavReader::open(&path).unwrap().duration() as f64 / 22050.0;
+
+        let mut messages = Vec::new();
+        let result = decode_file(&path, |message, offset| messages.push((message, offset)));
+        std::fs::remove_file(&path).unwrap();
+        result.unwrap();
+
+        assert_eq!(messages.len(), 2, "{:?}", messages);
+        let (SameMessage::StartOfMessage(decoded), start) = &messages[0] else {
+            panic!("expected a header first, got {:?}", messages[0]);
+        };
+        // The station ID comes back padded to eight characters.
+        let decoded = SameHeader::parse(decoded.as_str()).unwrap();
+        assert_eq!(decoded.alert_key(), header.alert_key());
+        assert_eq!(decoded.station_id.trim_end(), header.station_id);
+        assert!(matches!(messages[1].0, SameMessage::EndOfMessage));
+
+        // The header is framed after its third burst, well before the
+        // attention tone ends; the EOM at most at the end of the file.
+        let seconds = |offset: u64| offset as f64 / TARGET_SAMPLE_RATE as f64;
+        assert!(seconds(*start) < 8.0, "header at {:.2} s", seconds(*start));
+        assert!(seconds(messages[1].1) <= file_secs);
+        assert!(seconds(messages[1].1) > file_secs - 3.0);
+    }
+}
@@ -0,0 +1,197 @@
+use crate::fips;
+use crate::header::SameHeader;
+use crate::state::EasAlertData;
+use chrono::{DateTime, Duration, Utc};
+use chrono_tz::Tz;
+use phf::phf_map;
+
+static EVENTS: phf::Map<&'static str, &'static str> = phf_map! {
+    "ADR" => "Administrative Message",
+    "AVA" => "Avalanche Watch",
+    "AVW" => "Avalanche Warning",
+    "BHW" => "Biological Hazard Warning",
+    "BLU" => "Blue Alert",
+    "BWW" => "Boil Water Warning",
+    "BZW" => "Blizzard Warning",
+    "CAE" => "Child Abduction Emergency",
+    "CDW" => "Civil Danger Warning",
+    "CEM" => "Civil Emergency Message",
+    "CFA" => "Coastal Flood Watch",
+    "CFW" => "Coastal Flood Warning",
+    "CHW" => "Chemical Hazard Warning",
+    "CWW" => "Contaminated Water Warning",
+    "DBA" => "Dam Watch",
+    "DBW" => "Dam Break Warning",
+    "DEW" => "Contagious Disease Warning",
+    "DMO" => "Practice/Demo Warning",
+    "DSW" => "Dust Storm Warning",
+    "EAN" => "Emergency Action Notification",
+    "EAT" => "Emergency Action Termination",
+    "EQW" => "Earthquake Warning",
+    "EVI" => "Evacuation Immediate",
+    "EWW" => "Extreme Wind Warning",
+    "FCW" => "Food Contamination Warning",
+    "FFA" => "Flash Flood Watch",
+    "FFS" => "Flash Flood Statement",
+    "FFW" => "Flash Flood Warning",
+    "FLA" => "Flood Watch",
+    "FLS" => "Flood Statement",
+    "FLW" => "Flood Warning",
+    "FRW" => "Fire Warning",
+    "FSW" => "Flash Freeze Warning",
+    "FZW" => "Freeze Warning",
+    "HLS" => "Hurricane Local Statement",
+    "HMW" => "Hazardous Materials Warning",
+    "HUA" => "Hurricane Watch",
+    "HUW" => "Hurricane Warning",
+    "HWA" => "High Wind Watch",
+    "HWW" => "High Wind Warning",
+    "IBW" => "Iceberg Warning",
+    "IFW" => "Industrial Fire Warning",
+    "LAE" => "Local Area Emergency",
+    "LEW" => "Law Enforcement Warning",
+    "LSW" => "Land Slide Warning",
+    "MEP" => "Missing and Endangered Persons",
+    "NAT" => "National Audible Test",
+    "NIC" => "National Information Center",
+    "NMN" => "Network Message Notification",
+    "NPT" => "National Periodic Test",
+    "NST" => "National Silent Test",
+    "NUW" => "Nuclear Power Plant Warning",
+    "POS" => "Power Outage Statement",
+    "RHW" => "Radiological Hazard Warning",
+    "RMT" => "Required Monthly Test",
+    "RWT" => "Required Weekly Test",
+    "SMW" => "Special Marine Warning",
+    "SPS" => "Special Weather Statement",
+    "SPW" => "Shelter in Place Warning",
+    "SQW" => "Snow Squall Warning",
+    "SSA" => "Storm Surge Watch",
+    "SSW" => "Storm Surge Warning",
+    "SVA" => "Severe Thunderstorm Watch",
+    "SVR" => "Severe Thunderstorm Warning",
+    "SVS" => "Severe Weather Statement",
+    "TOA" => "Tornado Watch",
+    "TOE" => "911 Telephone Outage Emergency",
+    "TOR" => "Tornado Warning",
+    "TRA" => "Tropical Storm Watch",
+    "TRW" => "Tropical Storm Warning",
+    "TSA" => "Tsunami Watch",
+    "TSW" => "Tsunami Warning",
+    "TXB" => "Transmitter Backup On",
+    "TXF" => "Transmitter Carrier Off",
+    "TXO" => "Transmitter Carrier On",
+    "TXP" => "Transmitter Primary On",
+    "VOW" => "Volcano Warning",
+    "WFA" => "Wild Fire Watch",
+    "WFW" => "Wild Fire Warning",
+    "WSA" => "Winter Storm Watch",
+    "WSW" => "Winter Storm Warning",
+};
+
+static ORIGINATORS: phf::Map<&'static str, &'static str> = phf_map! {
+    "EAN" => "The Emergency Action Notification Network",
+    "PEP" => "The Primary Entry Point System",
+    "CIV" => "The Civil Authorities",
+    "WXR" => "The National Weather Service",
+    "EAS" => "An EAS Participant",
+};
+
+const SUBDIVISIONS: [&str; 10] = [
+    "",
+    "Northwest ",
+    "North ",
+    "Northeast ",
+    "West ",
+    "Central ",
+    "East ",
+    "Southwest ",
+    "South ",
+    "Southeast ",
+];
+
+pub fn event_text(code: &str) -> String {
+    EVENTS
+        .get(code)
+        .map(|text| text.to_string())
+        .unwrap_or_else(|| format!("Unknown Event ({})", code))
+}
+
+pub fn originator_text(code: &str) -> String {
+    ORIGINATORS
+        .get(code)
+        .map(|text| text.to_string())
+        .unwrap_or_else(|| format!("Unknown Originator ({})", code))
+}
+
+pub fn location_text(code: &str) -> String {
+    if code.len() != 6 || !code.bytes().all(|b| b.is_ascii_digit()) {
+        return format!("Unknown Location ({})", code);
+    }
+
+    let subdivision = SUBDIVISIONS[(code.as_bytes()[0] - b'0') as usize];
+    let state_code = &code[1..3];
+    let county_code = &code[3..6];
+
+    if state_code == "00" {
+        return "United States".to_string();
+    }
+
+    if let Some(area) = fips::marine_area(state_code) {
+        return format!("{}{} (Zone {})", subdivision, area, county_code);
+    }
+
+    let Some(state) = fips::state(state_code) else {
+        return format!("Unknown Location ({})", code);
+    };
+
+    if county_code == "000" {
+        return format!("All of {}", state.name);
+    }
+
+    match fips::county(state_code, county_code) {
+        Some(county) => format!("{}{}, {}", subdivision, county, state.abbreviation),
+        None => format!(
+            "{}Unknown County ({}), {}",
+            subdivision, county_code, state.abbreviation
+        ),
+    }
+}
+
+pub fn translate(header: &SameHeader, timezone: Tz) -> EasAlertData {
+    let start = header.issued_at(Utc::now()).unwrap_or_else(Utc::now);
+    let end = start + Duration::from_std(header.purge_duration()).unwrap_or_default();
+
+    let event_text = event_text(&header.event);
+    let originator_text = originator_text(&header.originator);
+    let locations: Vec<String> = header
+        .locations
+        .iter()
+        .map(|code| location_text(code))
+        .collect();
+
+    let eas_text = format!(
+        "{} has issued a {} for {}; beginning at {} and ending at {}. Message from {}.",
+        originator_text,
+        event_text,
+        locations.join("; "),
+        format_time(start, timezone),
+        format_time(end, timezone),
+        header.station_id.trim()
+    );
+
+    EasAlertData {
+        eas_text,
+        event_text,
+        event_code: header.event.clone(),
+        fips: header.locations.clone(),
+        locations: locations.join(", "),
+        originator: originator_text,
+    }
+}
+
+fn format_time(time: DateTime<Utc>, timezone: Tz) -> String {
+    time.with_timezone(&timezone)
+        .format("%I:%M %p %Z on %B %d, %Y")
+        .to_string()
+}
@@ -0,0 +1,101 @@
+use crate::header::{self, AttentionTone, SameHeader};
+use crate::media;
+use anyhow::{bail, Context, Result};
+use hound::{WavSpec, WavWriter};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+const DEFAULT_SAMPLE_RATE: u32 = 48000;
+const DEFAULT_AMPLITUDE: f64 = 0.79;
+const DEFAULT_ATTENTION_DURATION: Duration = Duration::from_secs(8);
+
+#[derive(Debug, Clone)]
+pub struct EncodeOptions {
+    pub sample_rate: u32,
+    pub amplitude: f64,
+    /// Silence after each header and EOM burst.
+    pub burst_gap: Duration,
+    pub attention_tone: Option<(AttentionTone, Duration)>,
+    pub voice: Option<PathBuf>,
+}
+
+impl Default for EncodeOptions {
+    fn default() -> Self {
+        Self {
+            sample_rate: DEFAULT_SAMPLE_RATE,
+            amplitude: DEFAULT_AMPLITUDE,
+            burst_gap: Duration::from_secs(1),
+            attention_tone: Some((AttentionTone::TwoTone, DEFAULT_ATTENTION_DURATION)),
+            voice: None,
+        }
+    }
+}
+
+/// Builds a complete SAME message: three header bursts, the optional attention
+/// tone and voice message, then three EOM bursts.
+pub fn generate_same_message(header: &SameHeader, options: &EncodeOptions) -> Result<Vec<i16>> {
+    if options.sample_rate < header::MIN_SAMPLE_RATE {
+        bail!(
+            "Sample rate must be at least {} Hz",
+            header::MIN_SAMPLE_RATE
+        );
+    }
+
+    let gap = vec![0i16; (options.burst_gap.as_secs_f64() * options.sample_rate as f64) as usize];
+
+    let mut out = header::generate_same_bursts(
+        &header.to_string(),
+        options.sample_rate,
+        options.amplitude,
+        options.burst_gap,
+    )?;
+
+    if let Some((tone, duration)) = options.attention_tone {
+        out.extend(header::generate_attention_tone(
+            tone,
+            duration,
+            options.sample_rate,
+            options.amplitude,
+        )?);
+        out.extend_from_slice(&gap);
+    }
+
+    if let Some(voice_path) = &options.voice {
+        let voice = media::decode_file_samples(voice_path, options.sample_rate)
+            .with_context(|| format!("Failed to decode voice file {}", voice_path.display()))?;
+        out.extend(
+            voice
+                .into_iter()
+                .map(|sample| (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16),
+        );
+        out.extend_from_slice(&gap);
+    }
+
+    out.extend(header::generate_same_bursts(
+        "NNNN",
+        options.sample_rate,
+        options.amplitude,
+        options.burst_gap,
+    )?);
+
+    Ok(out)
+}
+
+pub fn write_same_message(path: &Path, header: &SameHeader, options: &EncodeOptions) -> Result<()> {
+    let samples = generate_same_message(header, options)?;
+
+    let spec = WavSpec {
+        channels: 1,
+        sample_rate: options.sample_rate,
+        bits_per_sample: 16,
+        sample_format: hound::SampleFormat::Int,
+    };
+    let mut writer = WavWriter::create(path, spec)
+        .with_context(|| format!("Failed to create {}", path.display()))?;
+    for sample in samples {
+        writer.write_sample(sample)?;
+    }
+    writer.finalize()?;
+
+    Ok(())
+}
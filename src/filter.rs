@@ -1,137 +1,810 @@
-use lazy_static::lazy_static;
-use parking_lot::RwLock;
+use crate::config::Config;
+use crate::state::{ActiveAlert, EasAlertData};
+use anyhow::{anyhow, bail, Result};
+use chrono::{DateTime, Datelike, NaiveTime, Utc, Weekday};
+use chrono_tz::Tz;
+use sameold::{EventCode, SignificanceLevel};
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use std::collections::HashSet;
+use std::fmt;
+use std::time::Duration;
 use tracing::{error, warn};
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub const DEFAULT_FILTER_NAME: &str = "Default Filter";
+/// SAME event codes are always three characters.
+const EVENT_CODE_LEN: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum FilterAction {
     Ignore,
     Relay,
     Log,
 }
 
+impl FilterAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FilterAction::Ignore => "ignore",
+            FilterAction::Relay => "relay",
+            FilterAction::Log => "log",
+        }
+    }
+}
+
+/// The outcome of filtering one alert, attached to its `ActiveAlert` so that
+/// notifications, DASDEC and the Icecast relay all act on the same answer.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AlertDecision {
+    pub relevant: bool,
+    pub filters_enabled: bool,
+    pub rule: Option<String>,
+    pub action: FilterAction,
+    pub schedule: Option<String>,
+    pub notify: bool,
+    pub dasdec: bool,
+    /// Whether any relay destination takes the alert.
+    pub icecast: bool,
+    #[serde(default)]
+    pub destinations: Vec<DestinationDecision>,
+}
+
+impl AlertDecision {
+    pub fn rule_name(&self) -> &str {
+        self.rule.as_deref().unwrap_or(DEFAULT_FILTER_NAME)
+    }
+
+    pub fn destination(&self, name: &str) -> Option<&DestinationDecision> {
+        self.destinations
+            .iter()
+            .find(|destination| destination.name == name)
+    }
+}
+
+/// What one relay destination does with an alert. Destinations with their own
+/// filter rules are decided by those; the rest follow the global `FILTERS`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DestinationDecision {
+    pub name: String,
+    pub rule: Option<String>,
+    pub action: FilterAction,
+    pub relay: bool,
+    #[serde(default)]
+    pub hold: Option<HoldPolicy>,
+}
+
+/// What happens to a held relay when nobody approves or cancels it in time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HoldDefault {
+    Relay,
+    Cancel,
+}
+
+impl HoldDefault {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HoldDefault::Relay => "relay",
+            HoldDefault::Cancel => "cancel",
+        }
+    }
+}
+
+/// Delays a rule's relays so an operator can review them first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HoldPolicy {
+    pub secs: u64,
+    pub default: HoldDefault,
+}
+
+impl DestinationDecision {
+    pub fn rule_name(&self) -> &str {
+        self.rule.as_deref().unwrap_or(DEFAULT_FILTER_NAME)
+    }
+}
+
+impl Default for AlertDecision {
+    fn default() -> Self {
+        Self {
+            relevant: false,
+            filters_enabled: false,
+            rule: None,
+            action: FilterAction::Relay,
+            schedule: None,
+            notify: false,
+            dasdec: false,
+            icecast: false,
+            destinations: Vec::new(),
+        }
+    }
+}
+
+impl fmt::Display for AlertDecision {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "filter '{}' -> {} (relevant: {}, notify: {}, dasdec: {}, icecast: {}{})",
+            self.rule_name(),
+            self.action.as_str(),
+            self.relevant,
+            self.notify,
+            self.dasdec,
+            self.icecast,
+            if self.filters_enabled {
+                ""
+            } else {
+                ", filters disabled"
+            }
+        )
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 enum EventCodeMatcher {
     Exact(String),
     Wildcard,
+    /// Glob matched against the whole event code, where `*` matches any run
+    /// and `?` exactly one character, e.g. `*W` or `??T`.
+    Pattern(String),
+    Category(EventCategory),
+}
+
+impl EventCodeMatcher {
+    fn parse(pattern: &str) -> Option<Self> {
+        let pattern = pattern.trim();
+        if pattern == "*" {
+            return Some(EventCodeMatcher::Wildcard);
+        }
+        if let Some(category) = EventCategory::from_name(pattern) {
+            return Some(EventCodeMatcher::Category(category));
+        }
+
+        let normalized = normalize_event_code(pattern);
+        if normalized.is_empty()
+            || !normalized
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '*' || c == '?')
+        {
+            return None;
+        }
+        if normalized.contains(['*', '?']) {
+            Some(EventCodeMatcher::Pattern(normalized))
+        } else {
+            Some(EventCodeMatcher::Exact(normalized))
+        }
+    }
+
+    /// Whether any three-character event code could match. `?T`, for one,
+    /// only ever matches two characters.
+    fn fits_event_code(&self) -> bool {
+        match self {
+            EventCodeMatcher::Exact(code) => code.len() == EVENT_CODE_LEN,
+            EventCodeMatcher::Pattern(pattern) => {
+                let fixed = pattern.chars().filter(|&c| c != '*').count();
+                if pattern.contains('*') {
+                    fixed <= EVENT_CODE_LEN
+                } else {
+                    fixed == EVENT_CODE_LEN
+                }
+            }
+            EventCodeMatcher::Wildcard | EventCodeMatcher::Category(_) => true,
+        }
+    }
+
+    fn matches(&self, code: &str) -> bool {
+        match self {
+            EventCodeMatcher::Wildcard => true,
+            EventCodeMatcher::Exact(expected) => expected == code,
+            EventCodeMatcher::Pattern(pattern) => glob_matches(pattern.as_bytes(), code.as_bytes()),
+            EventCodeMatcher::Category(category) => category.contains(code),
+        }
+    }
+}
+
+/// Named classes of event codes. Membership comes from the SAME codebook in
+/// `sameold`, falling back to the last character of the code, so codes that
+/// are missing from our own tables still land in a sensible category.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum EventCategory {
+    Warnings,
+    Watches,
+    Advisories,
+    Statements,
+    Tests,
+    National,
+}
+
+impl EventCategory {
+    fn from_name(name: &str) -> Option<Self> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "warning" | "warnings" => Some(EventCategory::Warnings),
+            "watch" | "watches" => Some(EventCategory::Watches),
+            "advisory" | "advisories" | "emergency" | "emergencies" => {
+                Some(EventCategory::Advisories)
+            }
+            "statement" | "statements" => Some(EventCategory::Statements),
+            "test" | "tests" => Some(EventCategory::Tests),
+            "national" => Some(EventCategory::National),
+            _ => None,
+        }
+    }
+
+    fn contains(&self, code: &str) -> bool {
+        let event = EventCode::from(code);
+        let is_test = event.is_test();
+        match self {
+            EventCategory::Tests => is_test,
+            EventCategory::National => event.phenomenon().is_national(),
+            // Unknown significance is treated as a warning, as sameold advises.
+            EventCategory::Warnings => {
+                !is_test
+                    && matches!(
+                        event.significance(),
+                        SignificanceLevel::Warning | SignificanceLevel::Unknown
+                    )
+            }
+            EventCategory::Watches => !is_test && event.significance() == SignificanceLevel::Watch,
+            EventCategory::Advisories => {
+                !is_test && event.significance() == SignificanceLevel::Emergency
+            }
+            EventCategory::Statements => {
+                !is_test && event.significance() == SignificanceLevel::Statement
+            }
+        }
+    }
 }
 
+fn glob_matches(pattern: &[u8], code: &[u8]) -> bool {
+    match (pattern.first(), code.first()) {
+        (None, None) => true,
+        (Some(b'*'), _) => {
+            glob_matches(&pattern[1..], code)
+                || (!code.is_empty() && glob_matches(pattern, &code[1..]))
+        }
+        (Some(b'?'), Some(_)) => glob_matches(&pattern[1..], &code[1..]),
+        (Some(expected), Some(actual)) if expected == actual => {
+            glob_matches(&pattern[1..], &code[1..])
+        }
+        _ => false,
+    }
+}
+
+/// A filter rule matches an alert when every criterion it sets is satisfied.
+/// List criteria match if any of their entries does; unset criteria match
+/// everything.
 #[derive(Debug, Clone)]
 pub struct FilterRule {
     pub name: String,
     pub action: FilterAction,
     matchers: Vec<EventCodeMatcher>,
+    originators: Vec<String>,
+    fips: Vec<String>,
+    streams: Vec<String>,
+    station_ids: Vec<String>,
+    min_duration: Option<Duration>,
+    max_duration: Option<Duration>,
+    schedule: Option<FilterSchedule>,
+    pub hold: Option<HoldPolicy>,
 }
 
-impl FilterRule {
-    fn matches(&self, normalized_code: &str) -> bool {
-        self.matchers.iter().any(|matcher| match matcher {
-            EventCodeMatcher::Wildcard => true,
-            EventCodeMatcher::Exact(expected) => expected == normalized_code,
+/// Weekdays and local-time windows during which a rule is allowed to match.
+/// A window whose end is before its start runs past midnight and belongs to
+/// the day it started on.
+#[derive(Debug, Clone)]
+struct FilterSchedule {
+    days: Vec<Weekday>,
+    windows: Vec<(NaiveTime, NaiveTime)>,
+    timezone: Tz,
+}
+
+impl FilterSchedule {
+    fn is_active(&self, at: DateTime<Utc>) -> bool {
+        let local = at.with_timezone(&self.timezone);
+        let time = local.time();
+        let today = local.weekday();
+        let day_allowed = |day: Weekday| self.days.is_empty() || self.days.contains(&day);
+
+        if self.windows.is_empty() {
+            return day_allowed(today);
+        }
+
+        self.windows.iter().any(|&(start, end)| {
+            if start == end {
+                day_allowed(today)
+            } else if start < end {
+                day_allowed(today) && time >= start && time < end
+            } else {
+                (day_allowed(today) && time >= start) || (day_allowed(today.pred()) && time < end)
+            }
         })
     }
+
+    fn describe(&self) -> String {
+        let days = if self.days.is_empty() {
+            "Every day".to_string()
+        } else {
+            self.days
+                .iter()
+                .map(|day| day.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        let windows = if self.windows.is_empty() {
+            "all day".to_string()
+        } else {
+            self.windows
+                .iter()
+                .map(|(start, end)| format!("{}-{}", start.format("%H:%M"), end.format("%H:%M")))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        format!("{} {} ({})", days, windows, self.timezone)
+    }
 }
 
-lazy_static! {
-    static ref GLOBAL_FILTERS: RwLock<Vec<FilterRule>> = RwLock::new(Vec::new());
+impl FilterRule {
+    pub fn schedule_description(&self) -> Option<String> {
+        self.schedule.as_ref().map(FilterSchedule::describe)
+    }
+
+    fn matches(&self, alert: &ActiveAlert) -> bool {
+        let header = &alert.header;
+        let normalized_code = normalize_event_code(&header.event);
+
+        let event_matches = self
+            .matchers
+            .iter()
+            .any(|matcher| matcher.matches(&normalized_code));
+        let originator_matches =
+            self.originators.is_empty() || self.originators.contains(&header.originator);
+        let fips_matches = self.fips.is_empty()
+            || header
+                .locations
+                .iter()
+                .any(|location| self.fips.iter().any(|fips| fips_matches(fips, location)));
+        let stream_matches = self.streams.is_empty()
+            || alert
+                .sources
+                .iter()
+                .any(|source| self.streams.contains(source));
+        let station_matches = self.station_ids.is_empty()
+            || self
+                .station_ids
+                .iter()
+                .any(|station| station.eq_ignore_ascii_case(header.station_id.trim()));
+        let duration = header.purge_duration();
+        let duration_matches = self.min_duration.is_none_or(|min| duration >= min)
+            && self.max_duration.is_none_or(|max| duration <= max);
+
+        let schedule_matches = self
+            .schedule
+            .as_ref()
+            .is_none_or(|schedule| schedule.is_active(alert.received_at));
+
+        event_matches
+            && schedule_matches
+            && originator_matches
+            && fips_matches
+            && stream_matches
+            && station_matches
+            && duration_matches
+    }
 }
 
-pub fn parse_filters(config_json: &Value) -> Vec<FilterRule> {
-    let mut filters = Vec::new();
+/// Six-digit codes match exactly; five-digit county codes (SSCCC) match every
+/// subdivision of that county.
+fn fips_matches(pattern: &str, location: &str) -> bool {
+    if pattern.len() == 5 {
+        location.get(1..) == Some(pattern)
+    } else {
+        pattern == location
+    }
+}
 
-    let Some(entries) = config_json.get("FILTERS").and_then(Value::as_array) else {
-        return filters;
+pub fn parse_filters(config_json: &Value, timezone: Tz) -> Result<Vec<FilterRule>> {
+    let entries = match config_json.get("FILTERS") {
+        None | Some(Value::Null) => return Ok(Vec::new()),
+        Some(Value::Array(entries)) => entries,
+        Some(_) => bail!("FILTERS must be an array"),
     };
 
+    let stream_urls: Vec<&str> = config_json
+        .get("ICECAST_STREAM_URL_ARRAY")
+        .and_then(Value::as_array)
+        .map(|urls| urls.iter().filter_map(Value::as_str).collect())
+        .unwrap_or_default();
+
+    parse_filter_rules(entries, &stream_urls, timezone)
+}
+
+/// Reads `FILTER_DEFAULT_ACTION`, the action for alerts no rule matches. It
+/// defaults to `ignore`: unmatched alerts are logged and kept in the history
+/// but neither notified nor relayed.
+pub fn parse_default_action(config_json: &Value) -> Result<FilterAction> {
+    match config_json.get("FILTER_DEFAULT_ACTION") {
+        None | Some(Value::Null) => Ok(FilterAction::Ignore),
+        Some(Value::String(action)) => match action.trim().to_ascii_lowercase().as_str() {
+            "ignore" => Ok(FilterAction::Ignore),
+            "log" => Ok(FilterAction::Log),
+            "relay" => Ok(FilterAction::Relay),
+            other => bail!(
+                "FILTER_DEFAULT_ACTION must be ignore, log or relay, not '{}'",
+                other
+            ),
+        },
+        Some(other) => bail!("FILTER_DEFAULT_ACTION must be a string, not {}", other),
+    }
+}
+
+/// Parses a list of filter rules in the `FILTERS` format. A rule that cannot
+/// be used fails the whole list, so a typo never silently drops a rule.
+pub fn parse_filter_rules(
+    entries: &[Value],
+    stream_urls: &[&str],
+    timezone: Tz,
+) -> Result<Vec<FilterRule>> {
+    let mut filters = Vec::new();
+
     for entry in entries {
-        let Some(name) = entry.get("name").and_then(Value::as_str).map(str::trim) else {
-            warn!("Skipping filter without a valid name: {:?}", entry);
-            continue;
-        };
+        let name = entry
+            .get("name")
+            .and_then(Value::as_str)
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .ok_or_else(|| anyhow!("Filter without a valid name: {}", entry))?;
 
-        let Some(codes_value) = entry.get("event_codes").and_then(Value::as_array) else {
-            warn!("Skipping filter '{}' due to missing event_codes", name);
-            continue;
-        };
+        let originators = parse_string_list(entry, "originators", name)
+            .into_iter()
+            .map(|originator| originator.to_ascii_uppercase())
+            .collect::<Vec<_>>();
+        let fips = parse_string_list(entry, "fips", name)
+            .into_iter()
+            .filter(|code| {
+                let valid = matches!(code.len(), 5 | 6) && code.bytes().all(|b| b.is_ascii_digit());
+                if !valid {
+                    warn!(
+                        "Filter '{}' has invalid FIPS code '{}'; ignoring it",
+                        name, code
+                    );
+                }
+                valid
+            })
+            .collect::<Vec<_>>();
+        let streams = parse_streams(entry, name, stream_urls);
+        let station_ids = parse_string_list(entry, "station_ids", name);
+        let min_duration = parse_duration(entry, "min_duration", name);
+        let max_duration = parse_duration(entry, "max_duration", name);
+
+        let has_other_criteria = !originators.is_empty()
+            || !fips.is_empty()
+            || !streams.is_empty()
+            || !station_ids.is_empty()
+            || min_duration.is_some()
+            || max_duration.is_some();
+
+        let mut matchers = Vec::new();
+        match entry.get("event_codes").and_then(Value::as_array) {
+            Some(codes_value) => {
+                for code_value in codes_value {
+                    let Some(pattern) = code_value.as_str() else {
+                        continue;
+                    };
+                    match EventCodeMatcher::parse(pattern) {
+                        Some(matcher) => {
+                            if !matcher.fits_event_code() {
+                                warn!(
+                                    "Filter '{}' event code pattern '{}' can never match a three-character event code",
+                                    name, pattern
+                                );
+                            }
+                            matchers.push(matcher);
+                        }
+                        None if pattern.trim().is_empty() => {}
+                        None => warn!(
+                            "Filter '{}' has invalid event code pattern '{}'; ignoring it",
+                            name, pattern
+                        ),
+                    }
+                }
 
-        let mut matchers = Vec::with_capacity(codes_value.len());
-        for code_value in codes_value {
-            if let Some(pattern) = code_value.as_str() {
-                let pattern = pattern.trim();
-                if pattern == "*" {
-                    matchers.push(EventCodeMatcher::Wildcard);
-                } else if !pattern.is_empty() {
-                    matchers.push(EventCodeMatcher::Exact(normalize_event_code(pattern)));
+                if matchers.is_empty() {
+                    bail!("Filter '{}' has no valid event codes", name);
                 }
             }
+            None if has_other_criteria => matchers.push(EventCodeMatcher::Wildcard),
+            None => bail!("Filter '{}' is missing event_codes", name),
         }
 
-        if matchers.is_empty() {
-            warn!("Filter '{}' has no valid event codes; skipping", name);
-            continue;
-        }
+        let schedule = match entry.get("schedule") {
+            Some(value) => Some(parse_schedule(value, timezone).map_err(|reason| {
+                anyhow!("Filter '{}' has an invalid schedule ({})", name, reason)
+            })?),
+            None => None,
+        };
 
-        let Some(action_str) = entry.get("action").and_then(Value::as_str) else {
-            warn!(
-                "Filter '{}' missing action field; defaulting to relay",
-                name
-            );
-            filters.push(FilterRule {
-                name: name.to_string(),
-                action: FilterAction::Relay,
-                matchers,
-            });
-            continue;
+        let action = match entry.get("action").and_then(Value::as_str) {
+            Some(action_str) => parse_action(action_str, name),
+            None => {
+                warn!(
+                    "Filter '{}' missing action field; defaulting to relay",
+                    name
+                );
+                FilterAction::Relay
+            }
         };
 
-        let action = parse_action(action_str, name);
+        let hold = parse_hold(entry, name);
 
         filters.push(FilterRule {
             name: name.to_string(),
             action,
+            hold,
             matchers,
+            originators,
+            fips,
+            streams,
+            station_ids,
+            min_duration,
+            max_duration,
+            schedule,
         });
     }
 
-    filters
+    Ok(filters)
+}
+
+fn parse_string_list(entry: &Value, field: &str, filter_name: &str) -> Vec<String> {
+    let Some(value) = entry.get(field) else {
+        return Vec::new();
+    };
+    let Some(values) = value.as_array() else {
+        warn!(
+            "Filter '{}' field '{}' must be an array; ignoring it",
+            filter_name, field
+        );
+        return Vec::new();
+    };
+
+    values
+        .iter()
+        .filter_map(Value::as_str)
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Streams may be given as URLs or as 1-based monitor numbers, which refer to
+/// entries in `ICECAST_STREAM_URL_ARRAY`.
+fn parse_streams(entry: &Value, filter_name: &str, stream_urls: &[&str]) -> Vec<String> {
+    let Some(values) = entry.get("streams").and_then(Value::as_array) else {
+        return Vec::new();
+    };
+
+    let mut streams = Vec::new();
+    for value in values {
+        let monitor = match value {
+            Value::Number(number) => number.as_u64(),
+            Value::String(text) => text.trim().trim_start_matches('#').parse().ok(),
+            _ => None,
+        };
+
+        match (monitor, value.as_str()) {
+            (Some(number), _) => match stream_urls.get((number as usize).wrapping_sub(1)) {
+                Some(url) => streams.push(url.to_string()),
+                None => warn!(
+                    "Filter '{}' references monitor #{} which does not exist; ignoring it",
+                    filter_name, number
+                ),
+            },
+            (None, Some(url)) if !url.trim().is_empty() => streams.push(url.trim().to_string()),
+            _ => warn!(
+                "Filter '{}' has invalid stream entry {}; ignoring it",
+                filter_name, value
+            ),
+        }
+    }
+    streams
+}
+
+/// Durations are minutes as a number, or a SAME-style "HHMM" string.
+fn parse_duration(entry: &Value, field: &str, filter_name: &str) -> Option<Duration> {
+    let value = entry.get(field)?;
+    let minutes = match value {
+        Value::Number(number) => number.as_u64(),
+        Value::String(text) => {
+            let text = text.trim();
+            match (text.len(), text.bytes().all(|b| b.is_ascii_digit())) {
+                (4, true) => {
+                    let hours: u64 = text[..2].parse().ok()?;
+                    let minutes: u64 = text[2..].parse().ok()?;
+                    Some(hours * 60 + minutes)
+                }
+                _ => None,
+            }
+        }
+        _ => None,
+    };
+
+    if minutes.is_none() {
+        warn!(
+            "Filter '{}' field '{}' must be minutes or an HHMM string; ignoring it",
+            filter_name, field
+        );
+    }
+    minutes.map(|minutes| Duration::from_secs(minutes * 60))
 }
 
-pub fn install_filters(filters: Vec<FilterRule>) {
-    let mut global_filters = GLOBAL_FILTERS.write();
-    *global_filters = filters;
+/// Schedules look like `{"days": ["sat", "sun"], "windows": [{"start": "22:00",
+/// "end": "06:00"}]}`. A single window may be given with top-level `start` and
+/// `end` instead; `weekdays` and `weekends` expand to their days.
+fn parse_schedule(value: &Value, timezone: Tz) -> Result<FilterSchedule, String> {
+    let mut days = Vec::new();
+    if let Some(entries) = value.get("days") {
+        let entries = entries.as_array().ok_or("days must be an array")?;
+        for entry in entries {
+            let name = entry.as_str().ok_or("days must contain strings")?.trim();
+            match name.to_ascii_lowercase().as_str() {
+                "weekdays" => days.extend([
+                    Weekday::Mon,
+                    Weekday::Tue,
+                    Weekday::Wed,
+                    Weekday::Thu,
+                    Weekday::Fri,
+                ]),
+                "weekends" => days.extend([Weekday::Sat, Weekday::Sun]),
+                _ => days.push(
+                    name.parse::<Weekday>()
+                        .map_err(|_| format!("unknown day '{}'", name))?,
+                ),
+            }
+        }
+        days.sort_by_key(|day| day.num_days_from_monday());
+        days.dedup();
+    }
+
+    let window_values = match value.get("windows") {
+        Some(windows) => windows
+            .as_array()
+            .ok_or("windows must be an array")?
+            .iter()
+            .collect::<Vec<_>>(),
+        None if value.get("start").is_some() || value.get("end").is_some() => vec![value],
+        None => Vec::new(),
+    };
+
+    let mut windows = Vec::new();
+    for window in window_values {
+        let start = parse_time(window, "start")?;
+        let end = parse_time(window, "end")?;
+        windows.push((start, end));
+    }
+
+    if days.is_empty() && windows.is_empty() {
+        return Err("no days or windows given".to_string());
+    }
+
+    Ok(FilterSchedule {
+        days,
+        windows,
+        timezone,
+    })
 }
 
-#[allow(dead_code)]
-pub fn evaluate_action(filters: &[FilterRule], event_code: &str) -> FilterAction {
-    match_filter(filters, event_code)
-        .map(|rule| rule.action)
-        .unwrap_or(FilterAction::Relay)
+fn parse_time(window: &Value, field: &str) -> Result<NaiveTime, String> {
+    let text = window
+        .get(field)
+        .and_then(Value::as_str)
+        .ok_or_else(|| format!("window is missing '{}'", field))?;
+    NaiveTime::parse_from_str(text.trim(), "%H:%M")
+        .map_err(|_| format!("'{}' is not an HH:MM time", text))
 }
 
-pub fn determine_filter_name(event_code: &str) -> String {
-    let filters = GLOBAL_FILTERS.read();
-    match_filter(&filters, event_code)
-        .map(|rule| rule.name.clone())
-        .unwrap_or_else(|| "Default Filter".to_string())
+/// Decides once, when an alert is received, what every output should do with
+/// it. With `ENABLE_FILTERS` off every relevant alert is treated as a relay;
+/// with it on, alerts no rule matches get `FILTER_DEFAULT_ACTION`.
+pub fn decide(config: &Config, alert: &ActiveAlert) -> AlertDecision {
+    let relevant = is_alert_relevant(&alert.data, &config.watched_fips);
+    let unmatched_action = if config.enable_filters {
+        config.filter_default_action
+    } else {
+        FilterAction::Relay
+    };
+    let rule = if config.enable_filters {
+        match_filter(&config.filters, alert)
+    } else {
+        None
+    };
+    let action = rule.map(|rule| rule.action).unwrap_or(unmatched_action);
+
+    let notify = relevant && action != FilterAction::Ignore;
+    let relays = relevant && action == FilterAction::Relay;
+
+    let destinations: Vec<DestinationDecision> = config
+        .relay_destinations
+        .iter()
+        .map(|destination| {
+            let rule = match (&destination.filters, config.enable_filters) {
+                (Some(filters), true) => match_filter(filters, alert),
+                _ => rule,
+            };
+            let action = rule.map(|rule| rule.action).unwrap_or(unmatched_action);
+            let relay = relevant && config.should_relay && action == FilterAction::Relay;
+            DestinationDecision {
+                name: destination.name.clone(),
+                rule: rule.map(|rule| rule.name.clone()),
+                action,
+                relay,
+                hold: rule.and_then(|rule| rule.hold).filter(|_| relay),
+            }
+        })
+        .collect();
+
+    AlertDecision {
+        relevant,
+        filters_enabled: config.enable_filters,
+        rule: rule.map(|rule| rule.name.clone()),
+        action,
+        schedule: rule.and_then(FilterRule::schedule_description),
+        notify,
+        dasdec: relays && config.should_relay_dasdec && !config.dasdec_url.trim().is_empty(),
+        icecast: destinations.iter().any(|destination| destination.relay),
+        destinations,
+    }
 }
 
-pub fn match_filter<'a>(filters: &'a [FilterRule], event_code: &str) -> Option<&'a FilterRule> {
-    let normalized = normalize_event_code(event_code);
-    filters.iter().find(|rule| rule.matches(&normalized))
+fn is_alert_relevant(alert_data: &EasAlertData, watched_fips: &HashSet<String>) -> bool {
+    if watched_fips.is_empty() {
+        return true;
+    }
+    if alert_data.fips.iter().any(|fips| fips == "000000") {
+        return true;
+    }
+    alert_data
+        .fips
+        .iter()
+        .any(|fips| watched_fips.contains(fips))
 }
 
-pub fn should_relay_alert(event_code: &str) -> bool {
-    let filters = GLOBAL_FILTERS.read();
-    match_filter(&filters, event_code)
-        .map(|rule| rule.action != FilterAction::Ignore)
-        .unwrap_or(true)
+pub fn match_filter<'a>(filters: &'a [FilterRule], alert: &ActiveAlert) -> Option<&'a FilterRule> {
+    filters.iter().find(|rule| rule.matches(alert))
 }
 
-pub fn should_log_alert(event_code: &str) -> bool {
-    let filters = GLOBAL_FILTERS.read();
-    match_filter(&filters, event_code)
-        .map(|rule| rule.action == FilterAction::Log || rule.action == FilterAction::Relay)
-        .unwrap_or(false)
+/// `hold_secs` holds a rule's relays for operator review; `hold_default`
+/// (`relay` unless given) decides what happens when the hold runs out.
+fn parse_hold(entry: &Value, filter_name: &str) -> Option<HoldPolicy> {
+    let secs = match entry.get("hold_secs") {
+        None | Some(Value::Null) => return None,
+        Some(value) => match value.as_u64() {
+            Some(0) => return None,
+            Some(secs) => secs,
+            None => {
+                warn!(
+                    "Filter '{}' field 'hold_secs' must be a whole number of seconds; ignoring it",
+                    filter_name
+                );
+                return None;
+            }
+        },
+    };
+
+    let default = match entry.get("hold_default").and_then(Value::as_str) {
+        None => HoldDefault::Relay,
+        Some(text) => match text.trim().to_ascii_lowercase().as_str() {
+            "relay" | "approve" => HoldDefault::Relay,
+            "cancel" | "ignore" => HoldDefault::Cancel,
+            other => {
+                warn!(
+                    "Filter '{}' has unsupported hold_default '{}'; defaulting to relay",
+                    filter_name, other
+                );
+                HoldDefault::Relay
+            }
+        },
+    };
+
+    Some(HoldPolicy { secs, default })
 }
 
 fn parse_action(action: &str, filter_name: &str) -> FilterAction {
@@ -154,3 +827,306 @@ fn normalize_event_code(code: &str) -> String {
     normalized.make_ascii_uppercase();
     normalized
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eas_text;
+    use crate::header::SameHeader;
+    use chrono::TimeZone;
+    use serde_json::json;
+
+    fn alert(header: &str) -> ActiveAlert {
+        let header = SameHeader::parse(header).unwrap();
+        let data = eas_text::translate(&header, Tz::UTC);
+        let purge = header.purge_duration();
+        ActiveAlert::new(data, header, purge, String::new())
+    }
+
+    fn rules(filters: Value) -> Vec<FilterRule> {
+        parse_filter_rules(filters.as_array().unwrap(), &["http://a/1"], Tz::UTC).unwrap()
+    }
+
+    /// The example config with filters and relaying switched on.
+    fn example_config() -> Config {
+        let mut config_json: Value =
+            serde_json::from_str(include_str!("../config.example.json")).unwrap();
+        config_json["ENABLE_FILTERS"] = json!(true);
+        config_json["SHOULD_RELAY"] = json!(true);
+        Config::from_value(&config_json).unwrap()
+    }
+
+    fn matcher(pattern: &str) -> EventCodeMatcher {
+        EventCodeMatcher::parse(pattern).unwrap()
+    }
+
+    #[test]
+    fn globs_match_whole_event_codes() {
+        assert!(matcher("TO*").matches("TOR"));
+        assert!(!matcher("TO*").matches("T"));
+        assert!(!matcher("TO*").matches("SVR"));
+        assert!(matcher("*W").matches("FFW"));
+        assert!(!matcher("*W").matches("FFA"));
+        assert!(matcher("??T").matches("RWT"));
+        assert!(!matcher("??T").matches("WT"));
+        assert!(!matcher("??T").matches("RWTT"));
+        assert!(matcher("*").matches("ANY"));
+        assert!(matcher(" rwt ").matches("RWT"));
+        assert_eq!(EventCodeMatcher::parse("R-T"), None);
+    }
+
+    #[test]
+    fn flags_patterns_that_cannot_match() {
+        assert!(!matcher("?T").fits_event_code());
+        assert!(!matcher("TORR").fits_event_code());
+        assert!(!matcher("T?R?").fits_event_code());
+        assert!(matcher("??T").fits_event_code());
+        assert!(matcher("*W").fits_event_code());
+        assert!(matcher("warnings").fits_event_code());
+    }
+
+    #[test]
+    fn categories_follow_the_same_codebook() {
+        let warnings = matcher("warnings");
+        assert!(warnings.matches("TOR"));
+        assert!(warnings.matches("SVR"));
+        assert!(!warnings.matches("SVA"));
+        assert!(!warnings.matches("RWT"));
+        assert!(matcher("watches").matches("SVA"));
+        assert!(!matcher("watches").matches("TOR"));
+        assert!(matcher("statements").matches("SVS"));
+        assert!(matcher("tests").matches("RWT"));
+        assert!(matcher("tests").matches("RMT"));
+        assert!(!matcher("tests").matches("TOR"));
+        assert!(matcher("national").matches("EAN"));
+        assert!(!matcher("national").matches("TOR"));
+    }
+
+    #[test]
+    fn overnight_windows_cross_midnight() {
+        let schedule = parse_schedule(
+            &json!({ "windows": [{ "start": "22:00", "end": "06:00" }] }),
+            Tz::UTC,
+        )
+        .unwrap();
+        let at = |day: u32, hour: u32, minute: u32| {
+            Utc.with_ymd_and_hms(2026, 10, day, hour, minute, 0)
+                .unwrap()
+        };
+        assert!(schedule.is_active(at(16, 23, 0)));
+        assert!(schedule.is_active(at(16, 22, 0)));
+        assert!(schedule.is_active(at(17, 5, 59)));
+        assert!(!schedule.is_active(at(17, 6, 0)));
+        assert!(!schedule.is_active(at(16, 21, 59)));
+        assert!(!schedule.is_active(at(16, 12, 0)));
+    }
+
+    #[test]
+    fn overnight_windows_belong_to_the_day_they_start() {
+        let schedule = parse_schedule(
+            &json!({ "days": ["weekdays"], "start": "22:00", "end": "06:00" }),
+            Tz::UTC,
+        )
+        .unwrap();
+        // 2026-10-16 is a Friday.
+        let at = |day: u32, hour: u32| Utc.with_ymd_and_hms(2026, 10, day, hour, 30, 0).unwrap();
+        assert!(schedule.is_active(at(16, 23)));
+        assert!(schedule.is_active(at(17, 5)));
+        assert!(!schedule.is_active(at(17, 23)));
+        assert!(!schedule.is_active(at(19, 5)));
+        assert!(schedule.is_active(at(20, 5)));
+    }
+
+    #[test]
+    fn rejects_bad_schedules() {
+        assert!(parse_schedule(&json!({}), Tz::UTC).is_err());
+        assert!(parse_schedule(&json!({ "days": ["someday"] }), Tz::UTC).is_err());
+        assert!(parse_schedule(&json!({ "start": "25:00", "end": "06:00" }), Tz::UTC).is_err());
+        assert!(parse_schedule(&json!({ "start": "22:00" }), Tz::UTC).is_err());
+    }
+
+    #[test]
+    fn county_codes_match_every_subdivision() {
+        assert!(fips_matches("12057", "012057"));
+        assert!(fips_matches("12057", "312057"));
+        assert!(!fips_matches("12057", "012058"));
+        assert!(fips_matches("012057", "012057"));
+        assert!(!fips_matches("012057", "312057"));
+    }
+
+    #[test]
+    fn rules_combine_their_criteria() {
+        let filters = rules(json!([
+            {
+                "name": "Local",
+                "event_codes": ["TO*"],
+                "originators": ["wxr"],
+                "fips": ["12057"],
+                "max_duration": "0100",
+                "action": "relay"
+            },
+            { "name": "Rest", "event_codes": ["*"], "action": "log" }
+        ]));
+        let rule_for = |header: &str| match_filter(&filters, &alert(header)).unwrap().name.clone();
+
+        assert_eq!(
+            rule_for("ZCZC-WXR-TOR-012057+0030-1051700-KTBW/NWS-"),
+            "Local"
+        );
+        assert_eq!(
+            rule_for("ZCZC-WXR-TOR-099999-012057+0030-1051700-KTBW/NWS-"),
+            "Local"
+        );
+        assert_eq!(
+            rule_for("ZCZC-CIV-TOR-012057+0030-1051700-KTBW/NWS-"),
+            "Rest"
+        );
+        assert_eq!(
+            rule_for("ZCZC-WXR-TOR-012081+0030-1051700-KTBW/NWS-"),
+            "Rest"
+        );
+        assert_eq!(
+            rule_for("ZCZC-WXR-TOR-012057+0200-1051700-KTBW/NWS-"),
+            "Rest"
+        );
+        assert_eq!(
+            rule_for("ZCZC-WXR-SVR-012057+0030-1051700-KTBW/NWS-"),
+            "Rest"
+        );
+    }
+
+    #[test]
+    fn rejects_unusable_rules() {
+        let parse = |filters: Value| {
+            parse_filter_rules(filters.as_array().unwrap(), &[], Tz::UTC)
+                .err()
+                .map(|e| e.to_string())
+        };
+        assert!(parse(json!([{ "event_codes": ["TOR"] }]))
+            .unwrap()
+            .contains("without a valid name"));
+        assert!(parse(json!([{ "name": "A", "action": "log" }]))
+            .unwrap()
+            .contains("missing event_codes"));
+        assert!(parse(json!([{ "name": "A", "event_codes": ["R-T"] }]))
+            .unwrap()
+            .contains("no valid event codes"));
+        assert!(
+            parse(json!([{ "name": "A", "event_codes": ["*"], "schedule": {} }]))
+                .unwrap()
+                .contains("invalid schedule")
+        );
+        // Other criteria alone make a rule that matches every event code.
+        assert_eq!(
+            parse(json!([{ "name": "A", "originators": ["WXR"] }])),
+            None
+        );
+    }
+
+    #[test]
+    fn parses_hold_policies() {
+        let hold = |entry: Value| parse_hold(&entry, "test");
+        assert_eq!(hold(json!({})), None);
+        assert_eq!(hold(json!({ "hold_secs": 0 })), None);
+        assert_eq!(hold(json!({ "hold_secs": "soon" })), None);
+        assert_eq!(
+            hold(json!({ "hold_secs": 60 })),
+            Some(HoldPolicy {
+                secs: 60,
+                default: HoldDefault::Relay
+            })
+        );
+        assert_eq!(
+            hold(json!({ "hold_secs": 30, "hold_default": "Cancel" })),
+            Some(HoldPolicy {
+                secs: 30,
+                default: HoldDefault::Cancel
+            })
+        );
+    }
+
+    #[test]
+    fn decides_every_output_at_once() {
+        let config = example_config();
+
+        let warning = decide(
+            &config,
+            &alert("ZCZC-WXR-TOR-031055+0030-1051700-KOAX/NWS-"),
+        );
+        assert_eq!(warning.rule_name(), "National and Warnings");
+        assert!(warning.relevant && warning.notify && warning.icecast);
+        assert!(!warning.dasdec);
+        let public = warning.destination("public").unwrap();
+        assert_eq!(public.rule_name(), "Public Warnings");
+        assert!(public.relay);
+        assert_eq!(public.hold.map(|hold| hold.secs), Some(60));
+        let internal = warning.destination("internal").unwrap();
+        assert!(internal.relay && internal.hold.is_none());
+
+        let watch = decide(
+            &config,
+            &alert("ZCZC-WXR-SVA-031055+0300-1051700-KOAX/NWS-"),
+        );
+        assert_eq!(watch.action, FilterAction::Log);
+        assert!(watch.notify && !watch.icecast);
+        assert_eq!(
+            watch.destination("public").unwrap().rule_name(),
+            "Everything Else"
+        );
+
+        let test = decide(
+            &config,
+            &alert("ZCZC-WXR-RWT-031055+0030-1051700-KOAX/NWS-"),
+        );
+        assert_eq!(test.rule_name(), "RWT Filter");
+        assert!(!test.notify && !test.icecast);
+
+        let elsewhere = decide(
+            &config,
+            &alert("ZCZC-WXR-TOR-019001+0030-1051700-KDMX/NWS-"),
+        );
+        assert!(!elsewhere.relevant && !elsewhere.notify && !elsewhere.icecast);
+    }
+
+    #[test]
+    fn unmatched_alerts_get_the_default_action() {
+        let mut config = example_config();
+        config.filters.retain(|rule| rule.name == "RWT Filter");
+        config.relay_destinations[1].filters = None;
+        let header = "ZCZC-WXR-TOR-031055+0030-1051700-KOAX/NWS-";
+
+        let decision = decide(&config, &alert(header));
+        assert_eq!(decision.rule, None);
+        assert_eq!(decision.action, FilterAction::Ignore);
+        assert!(decision.relevant && !decision.notify && !decision.icecast);
+        assert!(decision
+            .destinations
+            .iter()
+            .all(|destination| destination.action == FilterAction::Ignore && !destination.relay));
+
+        config.filter_default_action = FilterAction::Log;
+        let decision = decide(&config, &alert(header));
+        assert_eq!(decision.action, FilterAction::Log);
+        assert!(decision.notify && !decision.icecast);
+
+        let default_action =
+            |value: Value| parse_default_action(&json!({ "FILTER_DEFAULT_ACTION": value })).ok();
+        assert_eq!(default_action(Value::Null), Some(FilterAction::Ignore));
+        assert_eq!(default_action(json!(" Relay ")), Some(FilterAction::Relay));
+        assert_eq!(default_action(json!("notify")), None);
+    }
+
+    #[test]
+    fn relays_everything_relevant_with_filters_disabled() {
+        let mut config = example_config();
+        config.enable_filters = false;
+        let decision = decide(
+            &config,
+            &alert("ZCZC-WXR-RWT-031055+0030-1051700-KOAX/NWS-"),
+        );
+        assert!(!decision.filters_enabled);
+        assert_eq!(decision.rule, None);
+        assert_eq!(decision.action, FilterAction::Relay);
+        assert!(decision.notify && decision.icecast);
+    }
+}
@@ -1,18 +1,76 @@
+use chrono::{DateTime, Datelike, NaiveDate, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
 use std::f64::consts::PI;
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
 
-const MARK_FREQ: f64 = 2083.3;
-const SPACE_FREQ: f64 = 1562.5;
-const MIN_SAMPLE_RATE: u32 = 8000;
-const BIT_DURATION_SEC: f64 = 0.00192;
+/// 520.83 baud, i.e. a 1.92 ms bit period.
+const BAUD_RATE: f64 = 3125.0 / 6.0;
+/// Mark and space are exactly four and three cycles per bit.
+const MARK_FREQ: f64 = BAUD_RATE * 4.0;
+const SPACE_FREQ: f64 = BAUD_RATE * 3.0;
+pub(crate) const MIN_SAMPLE_RATE: u32 = 8000;
 const PREAMBLE_BYTE: u8 = 0xD5;
 const BURST_COUNT: usize = 3;
+const MAX_LOCATIONS: usize = 31;
+/// Callsigns sameold accepts are 3 to 8 characters; generated headers pad
+/// them to the full 8.
+const MIN_STATION_ID_LEN: usize = 3;
+const STATION_ID_LEN: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AttentionTone {
+    /// The 853 Hz + 960 Hz dual tone used by broadcast EAS participants.
+    TwoTone,
+    /// The single 1050 Hz tone used by NOAA Weather Radio.
+    Nwr,
+}
+
+impl AttentionTone {
+    fn frequencies(&self) -> &'static [f64] {
+        match self {
+            AttentionTone::TwoTone => &[853.0, 960.0],
+            AttentionTone::Nwr => &[1050.0],
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            AttentionTone::TwoTone => "EAS two-tone (853/960 Hz)",
+            AttentionTone::Nwr => "NWR 1050 Hz tone",
+        }
+    }
+}
+
+impl FromStr for AttentionTone {
+    type Err = HeaderError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "eas" | "two-tone" | "twotone" => Ok(AttentionTone::TwoTone),
+            "nwr" | "1050" => Ok(AttentionTone::Nwr),
+            _ => Err(HeaderError::InvalidConfig(
+                "Attention tone must be one of: eas, nwr",
+            )),
+        }
+    }
+}
 
 #[derive(Debug)]
 pub enum HeaderError {
     InvalidConfig(&'static str),
+    InvalidHeader(HeaderParseError),
     Io(std::io::Error),
 }
 
+impl From<HeaderParseError> for HeaderError {
+    fn from(err: HeaderParseError) -> Self {
+        HeaderError::InvalidHeader(err)
+    }
+}
+
 impl From<std::io::Error> for HeaderError {
     fn from(err: std::io::Error) -> Self {
         HeaderError::Io(err)
@@ -23,6 +81,7 @@ impl std::fmt::Display for HeaderError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             HeaderError::InvalidConfig(msg) => f.write_str(msg),
+            HeaderError::InvalidHeader(err) => err.fmt(f),
             HeaderError::Io(err) => err.fmt(f),
         }
     }
@@ -32,64 +91,362 @@ impl std::error::Error for HeaderError {
     fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
         match self {
             HeaderError::Io(err) => Some(err),
+            HeaderError::InvalidHeader(err) => Some(err),
             HeaderError::InvalidConfig(_) => None,
         }
     }
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HeaderParseError {
+    MissingPrefix,
+    MissingTerminator,
+    NotAscii,
+    MissingPurgeTime,
+    InvalidOriginator(String),
+    InvalidEvent(String),
+    MissingLocations,
+    TooManyLocations(usize),
+    InvalidLocation(String),
+    InvalidPurgeTime(String),
+    InvalidIssueTime(String),
+    InvalidStationId(String),
+}
+
+impl fmt::Display for HeaderParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HeaderParseError::MissingPrefix => f.write_str("Header must start with 'ZCZC-'"),
+            HeaderParseError::MissingTerminator => f.write_str("Header must end with '-'"),
+            HeaderParseError::NotAscii => f.write_str("Header must be ASCII"),
+            HeaderParseError::MissingPurgeTime => {
+                f.write_str("Header is missing the '+TTTT' purge time")
+            }
+            HeaderParseError::InvalidOriginator(value) => {
+                write!(f, "Originator '{}' must be three uppercase letters", value)
+            }
+            HeaderParseError::InvalidEvent(value) => write!(
+                f,
+                "Event code '{}' must be three uppercase letters or digits",
+                value
+            ),
+            HeaderParseError::MissingLocations => {
+                f.write_str("Header must contain at least one location code")
+            }
+            HeaderParseError::TooManyLocations(count) => write!(
+                f,
+                "Header contains {} location codes; at most {} are allowed",
+                count, MAX_LOCATIONS
+            ),
+            HeaderParseError::InvalidLocation(value) => {
+                write!(f, "Location code '{}' must be six digits (PSSCCC)", value)
+            }
+            HeaderParseError::InvalidPurgeTime(value) => write!(
+                f,
+                "Purge time '{}' must be four digits (HHMM) with minutes below 60",
+                value
+            ),
+            HeaderParseError::InvalidIssueTime(value) => write!(
+                f,
+                "Issue time '{}' must be seven digits (JJJHHMM) with a valid day and time",
+                value
+            ),
+            HeaderParseError::InvalidStationId(value) => write!(
+                f,
+                "Station ID '{}' must be {} to {} characters without '+'",
+                value, MIN_STATION_ID_LEN, STATION_ID_LEN
+            ),
+        }
+    }
+}
+
+impl std::error::Error for HeaderParseError {}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct SameHeader {
+    pub originator: String,
+    pub event: String,
+    pub locations: Vec<String>,
+    pub purge_hours: u8,
+    pub purge_minutes: u8,
+    pub issue_day: u16,
+    pub issue_hour: u8,
+    pub issue_minute: u8,
+    pub station_id: String,
+}
+
+/// Identifies an alert independently of the station that sent it. Relays
+/// replace the station ID, so the same alert heard through two stations has
+/// two headers but one key.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct AlertKey {
+    originator: String,
+    event: String,
+    locations: Vec<String>,
+    purge: (u8, u8),
+    issued: (u16, u8, u8),
+}
+
+impl SameHeader {
+    pub fn parse(raw: &str) -> Result<Self, HeaderParseError> {
+        let raw = raw.trim();
+        if !raw.is_ascii() {
+            return Err(HeaderParseError::NotAscii);
+        }
+        let body = raw
+            .strip_prefix("ZCZC-")
+            .ok_or(HeaderParseError::MissingPrefix)?;
+        let body = body
+            .strip_suffix('-')
+            .ok_or(HeaderParseError::MissingTerminator)?;
+
+        let (codes, timing) = body
+            .split_once('+')
+            .ok_or(HeaderParseError::MissingPurgeTime)?;
+
+        let mut code_parts = codes.split('-');
+        let originator = code_parts.next().unwrap_or_default();
+        check_originator(originator)?;
+
+        let event = code_parts.next().unwrap_or_default();
+        check_event(event)?;
+
+        let locations: Vec<String> = code_parts.map(str::to_string).collect();
+        if locations.is_empty() {
+            return Err(HeaderParseError::MissingLocations);
+        }
+        if locations.len() > MAX_LOCATIONS {
+            return Err(HeaderParseError::TooManyLocations(locations.len()));
+        }
+        if let Some(invalid) = locations.iter().find(|code| !is_digits(code, 6)) {
+            return Err(HeaderParseError::InvalidLocation(invalid.clone()));
+        }
+
+        let mut timing_parts = timing.splitn(3, '-');
+        let purge = timing_parts.next().unwrap_or_default();
+        if !is_digits(purge, 4) || purge[2..].parse::<u8>().unwrap_or(60) >= 60 {
+            return Err(HeaderParseError::InvalidPurgeTime(purge.to_string()));
+        }
+
+        let issued = timing_parts.next().unwrap_or_default();
+        let issue_day: u16 = issued.get(..3).and_then(|v| v.parse().ok()).unwrap_or(0);
+        let issue_hour: u8 = issued.get(3..5).and_then(|v| v.parse().ok()).unwrap_or(24);
+        let issue_minute: u8 = issued.get(5..7).and_then(|v| v.parse().ok()).unwrap_or(60);
+        if !is_digits(issued, 7)
+            || !(1..=366).contains(&issue_day)
+            || issue_hour >= 24
+            || issue_minute >= 60
+        {
+            return Err(HeaderParseError::InvalidIssueTime(issued.to_string()));
+        }
+
+        // Callsigns such as "WABC-FM" contain '-', so the station ID is
+        // everything after the issue time.
+        let station_id = timing_parts.next().unwrap_or_default();
+        if !(MIN_STATION_ID_LEN..=STATION_ID_LEN).contains(&station_id.len())
+            || station_id
+                .bytes()
+                .any(|b| b == b'+' || (!b.is_ascii_graphic() && b != b' '))
+        {
+            return Err(HeaderParseError::InvalidStationId(station_id.to_string()));
+        }
+
+        Ok(Self {
+            originator: originator.to_string(),
+            event: event.to_string(),
+            locations,
+            purge_hours: purge[..2].parse().unwrap_or_default(),
+            purge_minutes: purge[2..].parse().unwrap_or_default(),
+            issue_day,
+            issue_hour,
+            issue_minute,
+            station_id: station_id.to_string(),
+        })
+    }
+
+    /// The header as we forward it: our own station ID, and optionally a
+    /// different originator or event code. Everything else is kept.
+    pub fn forwarded(
+        &self,
+        station_id: &str,
+        originator: Option<&str>,
+        event: Option<&str>,
+    ) -> Result<Self, HeaderParseError> {
+        let mut forwarded = self.clone();
+        forwarded.station_id = station_id.to_string();
+        if let Some(originator) = originator {
+            forwarded.originator = originator.to_string();
+        }
+        if let Some(event) = event {
+            forwarded.event = event.to_string();
+        }
+        SameHeader::parse(&forwarded.to_string())
+    }
+
+    pub fn alert_key(&self) -> AlertKey {
+        AlertKey {
+            originator: self.originator.clone(),
+            event: self.event.clone(),
+            locations: self.locations.clone(),
+            purge: (self.purge_hours, self.purge_minutes),
+            issued: (self.issue_day, self.issue_hour, self.issue_minute),
+        }
+    }
+
+    pub fn purge_duration(&self) -> Duration {
+        Duration::from_secs(self.purge_hours as u64 * 3600 + self.purge_minutes as u64 * 60)
+    }
+
+    pub fn issued_at(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        // JJJ carries no year, so an alert issued late in December and received
+        // after midnight on January 1st belongs to the previous year.
+        let mut year = now.year();
+        if u32::from(self.issue_day) > now.ordinal() + 1 {
+            year -= 1;
+        }
+
+        let naive = NaiveDate::from_yo_opt(year, u32::from(self.issue_day))?.and_hms_opt(
+            u32::from(self.issue_hour),
+            u32::from(self.issue_minute),
+            0,
+        )?;
+        Some(Utc.from_utc_datetime(&naive))
+    }
+}
+
+impl fmt::Display for SameHeader {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "ZCZC-{}-{}-{}+{:02}{:02}-{:03}{:02}{:02}-{}-",
+            self.originator,
+            self.event,
+            self.locations.join("-"),
+            self.purge_hours,
+            self.purge_minutes,
+            self.issue_day,
+            self.issue_hour,
+            self.issue_minute,
+            self.station_id
+        )
+    }
+}
+
+impl FromStr for SameHeader {
+    type Err = HeaderParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        SameHeader::parse(s)
+    }
+}
+
+pub fn check_originator(originator: &str) -> Result<(), HeaderParseError> {
+    if originator.len() != 3 || !originator.bytes().all(|b| b.is_ascii_uppercase()) {
+        return Err(HeaderParseError::InvalidOriginator(originator.to_string()));
+    }
+    Ok(())
+}
+
+pub fn check_event(event: &str) -> Result<(), HeaderParseError> {
+    if event.len() != 3
+        || !event
+            .bytes()
+            .all(|b| b.is_ascii_uppercase() || b.is_ascii_digit())
+    {
+        return Err(HeaderParseError::InvalidEvent(event.to_string()));
+    }
+    Ok(())
+}
+
+/// Turns a station name into an 8-character SAME station ID: uppercased,
+/// `+` (which would break the header) replaced with `/`, and cut or
+/// space-padded to length.
+pub fn station_id_from_name(name: &str) -> String {
+    let id: String = name
+        .trim()
+        .chars()
+        .filter(|c| c.is_ascii_graphic() || *c == ' ')
+        .map(|c| match c {
+            '+' => '/',
+            c => c.to_ascii_uppercase(),
+        })
+        .take(STATION_ID_LEN)
+        .collect();
+    format!("{:<width$}", id, width = STATION_ID_LEN)
+}
+
+fn is_digits(value: &str, len: usize) -> bool {
+    value.len() == len && value.bytes().all(|b| b.is_ascii_digit())
+}
+
 pub fn generate_same_header_samples(
     header: &str,
     sr: u32,
     amp: f64,
 ) -> Result<Vec<i16>, HeaderError> {
-    validate_header(header)?;
+    generate_same_bursts(header, sr, amp, Duration::from_secs(1))
+}
+
+pub fn generate_same_bursts(
+    header: &str,
+    sr: u32,
+    amp: f64,
+    gap: Duration,
+) -> Result<Vec<i16>, HeaderError> {
+    let header = on_air_header(header)?;
     validate_amplitude(amp)?;
 
     let sr = sr.max(MIN_SAMPLE_RATE);
 
-    let bits = build_same_bits(header);
-
-    let mut samples_per_bit = (sr as f64 * BIT_DURATION_SEC).floor() as usize;
-    if samples_per_bit < 1 {
-        samples_per_bit = 1;
-    }
-
-    let mark = make_tone_cycle(MARK_FREQ, sr, samples_per_bit, amp);
-    let space = make_tone_cycle(SPACE_FREQ, sr, samples_per_bit, amp);
+    let bits = build_same_bits(&header);
+    let burst = modulate_fsk(&bits, sr, amp);
 
-    let silence = vec![0i16; sr as usize];
-    let mut out: Vec<i16> = Vec::with_capacity(
-        (bits.len() * samples_per_bit * BURST_COUNT) + (silence.len() * BURST_COUNT),
-    );
+    let silence = vec![0i16; (gap.as_secs_f64() * sr as f64).round() as usize];
+    let mut out: Vec<i16> = Vec::with_capacity((burst.len() + silence.len()) * BURST_COUNT);
 
     for _ in 0..BURST_COUNT {
-        for &bit in &bits {
-            if bit == 1 {
-                out.extend_from_slice(&mark);
-            } else {
-                out.extend_from_slice(&space);
-            }
-        }
+        out.extend_from_slice(&burst);
         out.extend_from_slice(&silence);
     }
 
     Ok(out)
 }
 
-fn validate_header(header: &str) -> Result<(), HeaderError> {
-    if header.chars().count() == 4 && header == "NNNN" {
-        return Ok(());
-    }
-    if !header.starts_with("ZCZC-") {
-        return Err(HeaderError::InvalidConfig("Header must start with 'ZCZC-'"));
-    }
-    if !header.ends_with('-') {
-        return Err(HeaderError::InvalidConfig("Header must end with '-'"));
-    }
-    if !header.is_ascii() {
-        return Err(HeaderError::InvalidConfig("Header must be ASCII"));
+pub fn generate_attention_tone(
+    tone: AttentionTone,
+    duration: Duration,
+    sr: u32,
+    amp: f64,
+) -> Result<Vec<i16>, HeaderError> {
+    validate_amplitude(amp)?;
+
+    let sr = sr.max(MIN_SAMPLE_RATE);
+    let sr_f = sr as f64;
+    let freqs = tone.frequencies();
+    let per_tone_amp = amp / freqs.len() as f64;
+    let sample_count = (duration.as_secs_f64() * sr_f).round() as usize;
+
+    Ok((0..sample_count)
+        .map(|i| {
+            let t = i as f64 / sr_f;
+            let s: f64 = freqs
+                .iter()
+                .map(|freq| (2.0 * PI * freq * t).sin() * per_tone_amp)
+                .sum();
+            (s * i16::MAX as f64).clamp(i16::MIN as f64, i16::MAX as f64) as i16
+        })
+        .collect())
+}
+
+/// Validates `header` and returns it as transmitted, with a short station ID
+/// space-padded to 8 characters.
+fn on_air_header(header: &str) -> Result<String, HeaderError> {
+    if header == "NNNN" {
+        return Ok(header.to_string());
     }
-    Ok(())
+    let mut parsed = SameHeader::parse(header)?;
+    parsed.station_id = format!("{:<width$}", parsed.station_id, width = STATION_ID_LEN);
+    Ok(parsed.to_string())
 }
 
 fn validate_amplitude(amp: f64) -> Result<(), HeaderError> {
@@ -109,15 +466,15 @@ fn validate_amplitude(amp: f64) -> Result<(), HeaderError> {
 fn byte_to_bits_msb_first(b: u8) -> [u8; 8] {
     let mut bits = [0u8; 8];
     for j in (0..8).rev() {
-        bits[7 - j] = ((b >> j) & 1) as u8;
+        bits[7 - j] = (b >> j) & 1;
     }
     bits
 }
 
 fn byte_to_bits_lsb_first(b: u8) -> [u8; 8] {
     let mut bits = [0u8; 8];
-    for i in 0..8 {
-        bits[i] = ((b >> i) & 1) as u8;
+    for (i, bit) in bits.iter_mut().enumerate() {
+        *bit = (b >> i) & 1;
     }
     bits
 }
@@ -133,14 +490,194 @@ fn build_same_bits(header: &str) -> Vec<u8> {
     bits
 }
 
-fn make_tone_cycle(freq: f64, sr: u32, samples_per_bit: usize, amp: f64) -> Vec<i16> {
+/// AFSK-modulates `bits` with one running phase, so the waveform stays
+/// continuous across bit changes. Bit boundaries fall on the nearest sample
+/// to the exact 1.92 ms grid, so timing does not drift at rates where a bit
+/// is not a whole number of samples.
+fn modulate_fsk(bits: &[u8], sr: u32, amp: f64) -> Vec<i16> {
     let sr_f = sr as f64;
-    (0..samples_per_bit)
-        .map(|i| {
-            let t = i as f64 / sr_f;
-            let s = (2.0 * PI * freq * t).sin() * amp;
-            let v = (s * i16::MAX as f64).clamp(i16::MIN as f64, i16::MAX as f64);
-            v as i16
-        })
-        .collect()
+    let samples_per_bit = sr_f / BAUD_RATE;
+    let total = (bits.len() as f64 * samples_per_bit).round() as usize;
+    let mut out = Vec::with_capacity(total);
+    let mut phase = 0.0f64;
+    let mut start = 0usize;
+
+    for (idx, &bit) in bits.iter().enumerate() {
+        let end = ((idx + 1) as f64 * samples_per_bit).round() as usize;
+        let freq = if bit == 1 { MARK_FREQ } else { SPACE_FREQ };
+        let step = 2.0 * PI * freq / sr_f;
+        for _ in start..end {
+            let s = phase.sin() * amp * i16::MAX as f64;
+            out.push(s.clamp(i16::MIN as f64, i16::MAX as f64) as i16);
+            phase = (phase + step) % (2.0 * PI);
+        }
+        start = end;
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sameold::{Message, SameReceiverBuilder};
+
+    const HEADERS: &[&str] = &[
+        "ZCZC-WXR-TOR-029037+0030-1051700-KEAX/NWS-",
+        "ZCZC-EAS-RWT-012057-012081-012101-012103-012115+0100-0241500-WXYZ/FM -",
+        "ZCZC-CIV-CEM-048453-048491+0600-3651159-AUSTINTX-",
+    ];
+    const SAMPLE_RATES: &[u32] = &[8000, 11025, 16000, 22050, 44100, 48000];
+    const AMPLITUDES: &[f64] = &[0.1, 0.5, 0.79, 1.0];
+
+    /// Runs `samples` through sameold over a low noise floor, with a second
+    /// of noise before and three after.
+    fn decode(samples: &[i16], sr: u32, amp: f64) -> Vec<Message> {
+        let lead = sr as usize;
+        let tail = sr as usize * 3;
+        let mut audio = vec![0.0f32; lead];
+        audio.extend(samples.iter().map(|&s| s as f32 / i16::MAX as f32));
+        audio.resize(lead + samples.len() + tail, 0.0);
+
+        let mut seed = 0x2545_f491_u32;
+        for sample in &mut audio {
+            seed ^= seed << 13;
+            seed ^= seed >> 17;
+            seed ^= seed << 5;
+            *sample += (seed as f32 / u32::MAX as f32 - 0.5) * 0.01;
+        }
+
+        // A receiver on a live stream has its AGC settled on the program
+        // level long before an alert arrives; sameold's starts from zero gain
+        // and needs tens of seconds to climb, so start it where it settles.
+        let mut receiver = SameReceiverBuilder::new(sr)
+            .with_agc_gain_limits(1.0 / amp as f32, 1.0e6)
+            .build();
+        receiver.iter_messages(audio).collect()
+    }
+
+    #[test]
+    fn headers_round_trip_through_sameold() {
+        for header in HEADERS {
+            for &sr in SAMPLE_RATES {
+                for &amp in AMPLITUDES {
+                    let samples = generate_same_header_samples(header, sr, amp).unwrap();
+                    let messages = decode(&samples, sr, amp);
+                    let decoded: Vec<&str> = messages
+                        .iter()
+                        .filter_map(|msg| match msg {
+                            Message::StartOfMessage(decoded) => Some(decoded.as_str()),
+                            Message::EndOfMessage => None,
+                        })
+                        .collect();
+                    assert_eq!(
+                        decoded,
+                        vec![*header],
+                        "header did not round-trip at {} Hz, amplitude {}",
+                        sr,
+                        amp
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn end_of_message_round_trips_through_sameold() {
+        for &sr in SAMPLE_RATES {
+            for &amp in AMPLITUDES {
+                let samples = generate_same_header_samples("NNNN", sr, amp).unwrap();
+                let messages = decode(&samples, sr, amp);
+                assert!(
+                    matches!(messages.as_slice(), [Message::EndOfMessage]),
+                    "NNNN did not round-trip at {} Hz, amplitude {}: {:?}",
+                    sr,
+                    amp,
+                    messages
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn parses_short_and_hyphenated_callsigns() {
+        for (raw, station) in [
+            ("ZCZC-EAS-RWT-029095+0030-2901830-WABC-FM-", "WABC-FM"),
+            ("ZCZC-WXR-TOR-029037+0030-1051700-KEAX-", "KEAX"),
+            ("ZCZC-CIV-CEM-048453+0600-3651159-ABC-", "ABC"),
+            ("ZCZC-EAS-RWT-029095+0030-2901830-KEAX/NWS-", "KEAX/NWS"),
+        ] {
+            let header = SameHeader::parse(raw).unwrap();
+            assert_eq!(header.station_id, station, "{}", raw);
+            assert_eq!(header.to_string(), raw);
+        }
+    }
+
+    #[test]
+    fn rejects_out_of_range_callsigns() {
+        for raw in [
+            "ZCZC-EAS-RWT-029095+0030-2901830-AB-",
+            "ZCZC-EAS-RWT-029095+0030-2901830-KEAX/NWS1-",
+            "ZCZC-EAS-RWT-029095+0030-2901830-WABC-FM-X-",
+            "ZCZC-EAS-RWT-029095+0030-2901830-WA+C-",
+        ] {
+            assert!(
+                matches!(
+                    SameHeader::parse(raw),
+                    Err(HeaderParseError::InvalidStationId(_))
+                ),
+                "{}",
+                raw
+            );
+        }
+    }
+
+    #[test]
+    fn short_callsigns_are_padded_when_generated() {
+        let header = "ZCZC-EAS-RWT-029095+0030-2901830-WABC-FM-";
+        assert_eq!(
+            on_air_header(header).unwrap(),
+            "ZCZC-EAS-RWT-029095+0030-2901830-WABC-FM -"
+        );
+        let samples = generate_same_bursts(header, 22050, 0.5, Duration::ZERO).unwrap();
+        let bits = build_same_bits(&on_air_header(header).unwrap());
+        assert_eq!(
+            samples.len(),
+            modulate_fsk(&bits, 22050, 0.5).len() * BURST_COUNT
+        );
+    }
+
+    #[test]
+    fn bursts_follow_the_exact_bit_period() {
+        let header = HEADERS[0];
+        let bit_count = (16 + header.len()) * 8;
+        for &sr in SAMPLE_RATES {
+            let samples = generate_same_bursts(header, sr, 0.5, Duration::ZERO).unwrap();
+            let expected = (bit_count as f64 * sr as f64 / BAUD_RATE).round() as usize;
+            assert_eq!(samples.len(), expected * BURST_COUNT, "at {} Hz", sr);
+        }
+    }
+
+    #[test]
+    fn waveform_is_phase_continuous() {
+        let bits = build_same_bits(HEADERS[0]);
+        for &sr in SAMPLE_RATES {
+            let amp = 0.79;
+            let samples = modulate_fsk(&bits, sr, amp);
+            // The largest step a continuous mark tone can take between two
+            // samples, plus a little room for rounding.
+            let max_step = 2.0 * PI * MARK_FREQ / sr as f64 * amp * i16::MAX as f64 + 2.0;
+            let worst = samples
+                .windows(2)
+                .map(|pair| (pair[1] as f64 - pair[0] as f64).abs())
+                .fold(0.0, f64::max);
+            assert!(
+                worst <= max_step,
+                "discontinuity of {} at {} Hz (limit {})",
+                worst,
+                sr,
+                max_step
+            );
+        }
+    }
 }
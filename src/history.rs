@@ -0,0 +1,374 @@
+use crate::filter::AlertDecision;
+use crate::header::SameHeader;
+use crate::relay::RelayResult;
+use crate::state::{ActiveAlert, EasAlertData};
+use crate::webhook::NotificationResult;
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Utc};
+use parking_lot::Mutex;
+use rusqlite::types::{Type, Value as SqlValue};
+use rusqlite::{params, params_from_iter, Connection, OptionalExtension, Row};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+const HISTORY_DB_FILE: &str = "alert_history.db";
+const DEFAULT_PAGE_SIZE: u32 = 50;
+const MAX_PAGE_SIZE: u32 = 500;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS alerts (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    received_at INTEGER NOT NULL,
+    expires_at INTEGER NOT NULL,
+    raw_header TEXT NOT NULL,
+    originator TEXT NOT NULL,
+    event_code TEXT NOT NULL,
+    header_json TEXT NOT NULL,
+    data_json TEXT NOT NULL,
+    sources_json TEXT NOT NULL,
+    watched INTEGER NOT NULL,
+    filter_name TEXT NOT NULL,
+    filter_action TEXT NOT NULL,
+    recording_path TEXT,
+    notifications_json TEXT,
+    decision_json TEXT,
+    relays_json TEXT
+);
+CREATE INDEX IF NOT EXISTS alerts_received_at ON alerts (received_at);
+CREATE INDEX IF NOT EXISTS alerts_event_code ON alerts (event_code);
+CREATE INDEX IF NOT EXISTS alerts_originator ON alerts (originator);
+CREATE TABLE IF NOT EXISTS alert_locations (
+    alert_id INTEGER NOT NULL REFERENCES alerts (id) ON DELETE CASCADE,
+    fips TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS alert_locations_fips ON alert_locations (fips);
+CREATE INDEX IF NOT EXISTS alert_locations_alert_id ON alert_locations (alert_id);
+";
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HistoryRecord {
+    pub id: i64,
+    #[serde(with = "chrono::serde::ts_seconds")]
+    pub received_at: DateTime<Utc>,
+    #[serde(with = "chrono::serde::ts_seconds")]
+    pub expires_at: DateTime<Utc>,
+    pub raw_header: String,
+    pub header: SameHeader,
+    pub data: EasAlertData,
+    pub sources: Vec<String>,
+    pub watched: bool,
+    pub filter_name: String,
+    pub filter_action: String,
+    pub decision: Option<AlertDecision>,
+    pub recording_path: Option<PathBuf>,
+    pub notifications: Vec<NotificationResult>,
+    pub relays: Vec<RelayResult>,
+}
+
+/// Search parameters for `/api/history`. Times are Unix seconds, matching the
+/// `received_at` field in API payloads.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct HistoryQuery {
+    pub from: Option<i64>,
+    pub to: Option<i64>,
+    pub event: Option<String>,
+    pub originator: Option<String>,
+    pub fips: Option<String>,
+    pub page: Option<u32>,
+    pub per_page: Option<u32>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HistoryPage {
+    pub total: u64,
+    pub page: u32,
+    pub per_page: u32,
+    pub alerts: Vec<HistoryRecord>,
+}
+
+#[derive(Clone)]
+pub struct AlertHistory {
+    conn: Arc<Mutex<Connection>>,
+}
+
+impl AlertHistory {
+    pub fn open(state_dir: &Path) -> Result<Self> {
+        std::fs::create_dir_all(state_dir)?;
+        let path = state_dir.join(HISTORY_DB_FILE);
+        let conn = Connection::open(&path)
+            .with_context(|| format!("Failed to open alert history at {}", path.display()))?;
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        conn.pragma_update(None, "foreign_keys", "ON")?;
+        conn.execute_batch(SCHEMA)
+            .context("Failed to initialize alert history schema")?;
+
+        Ok(Self {
+            conn: Arc::new(Mutex::new(conn)),
+        })
+    }
+
+    async fn with_conn<T, F>(&self, f: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Connection) -> Result<T> + Send + 'static,
+    {
+        let conn = self.conn.clone();
+        tokio::task::spawn_blocking(move || f(&mut conn.lock()))
+            .await
+            .map_err(|e| anyhow!("Alert history task failed: {}", e))?
+    }
+
+    pub async fn record_alert(&self, alert: &ActiveAlert) -> Result<i64> {
+        let header_json = serde_json::to_string(&alert.header)?;
+        let data_json = serde_json::to_string(&alert.data)?;
+        let sources_json = serde_json::to_string(&alert.sources)?;
+        let received_at = alert.received_at.timestamp();
+        let expires_at = alert.expires_at.timestamp();
+        let raw_header = alert.raw_header.clone();
+        let originator = alert.header.originator.clone();
+        let event_code = alert.header.event.clone();
+        let locations = alert.header.locations.clone();
+        let watched = alert.decision.relevant;
+        let filter_name = alert.decision.rule_name().to_string();
+        let filter_action = alert.decision.action;
+        let decision_json = serde_json::to_string(&alert.decision)?;
+
+        self.with_conn(move |conn| {
+            let tx = conn.transaction()?;
+            tx.execute(
+                "INSERT INTO alerts (received_at, expires_at, raw_header, originator, event_code, \
+                 header_json, data_json, sources_json, watched, filter_name, filter_action, \
+                 decision_json) \
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
+                params![
+                    received_at,
+                    expires_at,
+                    raw_header,
+                    originator,
+                    event_code,
+                    header_json,
+                    data_json,
+                    sources_json,
+                    watched,
+                    filter_name,
+                    filter_action.as_str(),
+                    decision_json,
+                ],
+            )?;
+            let id = tx.last_insert_rowid();
+            {
+                let mut insert_location =
+                    tx.prepare("INSERT INTO alert_locations (alert_id, fips) VALUES (?1, ?2)")?;
+                for fips in &locations {
+                    insert_location.execute(params![id, fips])?;
+                }
+            }
+            tx.commit()?;
+            Ok(id)
+        })
+        .await
+    }
+
+    pub async fn add_source(&self, id: i64, source_stream: &str) -> Result<()> {
+        let source_stream = source_stream.to_string();
+        self.with_conn(move |conn| {
+            let tx = conn.transaction()?;
+            let sources_json: String = tx.query_row(
+                "SELECT sources_json FROM alerts WHERE id = ?1",
+                params![id],
+                |row| row.get(0),
+            )?;
+            let mut sources: Vec<String> = serde_json::from_str(&sources_json)?;
+            if !sources.contains(&source_stream) {
+                sources.push(source_stream);
+                tx.execute(
+                    "UPDATE alerts SET sources_json = ?1 WHERE id = ?2",
+                    params![serde_json::to_string(&sources)?, id],
+                )?;
+            }
+            tx.commit()?;
+            Ok(())
+        })
+        .await
+    }
+
+    pub async fn set_recording_path(&self, id: i64, path: &Path) -> Result<()> {
+        let path = path.to_string_lossy().into_owned();
+        self.with_conn(move |conn| {
+            conn.execute(
+                "UPDATE alerts SET recording_path = ?1 WHERE id = ?2",
+                params![path, id],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    pub async fn set_notifications(&self, id: i64, results: &[NotificationResult]) -> Result<()> {
+        let results_json = serde_json::to_string(results)?;
+        self.with_conn(move |conn| {
+            conn.execute(
+                "UPDATE alerts SET notifications_json = ?1 WHERE id = ?2",
+                params![results_json, id],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    pub async fn set_relay_results(&self, id: i64, results: &[RelayResult]) -> Result<()> {
+        let results_json = serde_json::to_string(results)?;
+        self.with_conn(move |conn| {
+            conn.execute(
+                "UPDATE alerts SET relays_json = ?1 WHERE id = ?2",
+                params![results_json, id],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    pub async fn get(&self, id: i64) -> Result<Option<HistoryRecord>> {
+        self.with_conn(move |conn| {
+            let record = conn
+                .query_row(
+                    &format!("SELECT {} FROM alerts WHERE id = ?1", RECORD_COLUMNS),
+                    params![id],
+                    read_record,
+                )
+                .optional()?;
+            Ok(record)
+        })
+        .await
+    }
+
+    pub async fn search(&self, query: HistoryQuery) -> Result<HistoryPage> {
+        self.with_conn(move |conn| {
+            let mut clauses: Vec<&str> = Vec::new();
+            let mut values: Vec<SqlValue> = Vec::new();
+
+            if let Some(from) = query.from {
+                clauses.push("received_at >= ?");
+                values.push(SqlValue::Integer(from));
+            }
+            if let Some(to) = query.to {
+                clauses.push("received_at <= ?");
+                values.push(SqlValue::Integer(to));
+            }
+            if let Some(event) = non_empty(&query.event) {
+                clauses.push("event_code = ?");
+                values.push(SqlValue::Text(event.to_ascii_uppercase()));
+            }
+            if let Some(originator) = non_empty(&query.originator) {
+                clauses.push("originator = ?");
+                values.push(SqlValue::Text(originator.to_ascii_uppercase()));
+            }
+            if let Some(fips) = non_empty(&query.fips) {
+                // Five-digit codes (SSCCC) match every subdivision of a county.
+                if fips.len() == 5 {
+                    clauses.push(
+                        "EXISTS (SELECT 1 FROM alert_locations l \
+                         WHERE l.alert_id = alerts.id AND substr(l.fips, 2) = ?)",
+                    );
+                } else {
+                    clauses.push(
+                        "EXISTS (SELECT 1 FROM alert_locations l \
+                         WHERE l.alert_id = alerts.id AND l.fips = ?)",
+                    );
+                }
+                values.push(SqlValue::Text(fips.to_string()));
+            }
+
+            let where_sql = if clauses.is_empty() {
+                String::new()
+            } else {
+                format!("WHERE {}", clauses.join(" AND "))
+            };
+
+            let total: i64 = conn.query_row(
+                &format!("SELECT COUNT(*) FROM alerts {}", where_sql),
+                params_from_iter(values.iter()),
+                |row| row.get(0),
+            )?;
+
+            let per_page = query
+                .per_page
+                .unwrap_or(DEFAULT_PAGE_SIZE)
+                .clamp(1, MAX_PAGE_SIZE);
+            let page = query.page.unwrap_or(1).max(1);
+            let offset = (page as i64 - 1) * per_page as i64;
+
+            let mut stmt = conn.prepare(&format!(
+                "SELECT {} FROM alerts {} ORDER BY received_at DESC, id DESC LIMIT {} OFFSET {}",
+                RECORD_COLUMNS, where_sql, per_page, offset
+            ))?;
+            let alerts = stmt
+                .query_map(params_from_iter(values.iter()), read_record)?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+
+            Ok(HistoryPage {
+                total: total as u64,
+                page,
+                per_page,
+                alerts,
+            })
+        })
+        .await
+    }
+}
+
+const RECORD_COLUMNS: &str = "id, received_at, expires_at, raw_header, header_json, data_json, \
+     sources_json, watched, filter_name, filter_action, recording_path, notifications_json, \
+     decision_json, relays_json";
+
+fn read_record(row: &Row<'_>) -> rusqlite::Result<HistoryRecord> {
+    let recording_path: Option<String> = row.get(10)?;
+    let notifications: Option<Vec<NotificationResult>> = optional_json_column(row, 11)?;
+    let relays: Option<Vec<RelayResult>> = optional_json_column(row, 13)?;
+
+    Ok(HistoryRecord {
+        id: row.get(0)?,
+        received_at: timestamp_column(row, 1)?,
+        expires_at: timestamp_column(row, 2)?,
+        raw_header: row.get(3)?,
+        header: json_column(row, 4)?,
+        data: json_column(row, 5)?,
+        sources: json_column(row, 6)?,
+        watched: row.get(7)?,
+        filter_name: row.get(8)?,
+        filter_action: row.get(9)?,
+        decision: optional_json_column(row, 12)?,
+        recording_path: recording_path.map(PathBuf::from),
+        notifications: notifications.unwrap_or_default(),
+        relays: relays.unwrap_or_default(),
+    })
+}
+
+fn json_column<T: DeserializeOwned>(row: &Row<'_>, idx: usize) -> rusqlite::Result<T> {
+    let json: String = row.get(idx)?;
+    serde_json::from_str(&json)
+        .map_err(|e| rusqlite::Error::FromSqlConversionFailure(idx, Type::Text, Box::new(e)))
+}
+
+fn optional_json_column<T: DeserializeOwned>(
+    row: &Row<'_>,
+    idx: usize,
+) -> rusqlite::Result<Option<T>> {
+    let json: Option<String> = row.get(idx)?;
+    json.map(|json| {
+        serde_json::from_str(&json)
+            .map_err(|e| rusqlite::Error::FromSqlConversionFailure(idx, Type::Text, Box::new(e)))
+    })
+    .transpose()
+}
+
+fn timestamp_column(row: &Row<'_>, idx: usize) -> rusqlite::Result<DateTime<Utc>> {
+    let secs: i64 = row.get(idx)?;
+    DateTime::from_timestamp(secs, 0).ok_or(rusqlite::Error::IntegralValueOutOfRange(idx, secs))
+}
+
+fn non_empty(value: &Option<String>) -> Option<&str> {
+    value.as_deref().map(str::trim).filter(|v| !v.is_empty())
+}
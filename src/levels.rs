@@ -0,0 +1,426 @@
+use crate::config::{Config, ConfigHandle};
+use crate::monitoring::MonitoringHub;
+use crate::webhook::send_status_notification;
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::time::Duration;
+use tokio::sync::mpsc::Receiver;
+use tracing::{info, warn};
+
+/// Each stream publishes one reading per window.
+const METER_WINDOW: Duration = Duration::from_secs(1);
+/// Samples at or beyond this magnitude count as clipped.
+const CLIP_LEVEL: f32 = 0.999;
+/// A window with at least this share of clipped samples is clipping.
+const CLIP_SHARE: f32 = 0.001;
+/// Reported level for digital silence.
+const FLOOR_DBFS: f32 = -120.0;
+
+/// RMS and peak level of one meter window, in dBFS.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct AudioLevels {
+    pub rms_dbfs: f32,
+    pub peak_dbfs: f32,
+    pub clipped_samples: u32,
+    pub clipping: bool,
+}
+
+/// A meter reading sent from a decoder to the level monitor.
+#[derive(Debug, Clone)]
+pub struct LevelReading {
+    pub stream: String,
+    pub levels: AudioLevels,
+}
+
+/// Accumulates decoded mono samples into fixed windows.
+pub struct LevelMeter {
+    window_len: u32,
+    fill: u32,
+    sum_squares: f64,
+    peak: f32,
+    clipped: u32,
+}
+
+impl LevelMeter {
+    pub fn new(sample_rate: u32) -> Self {
+        Self {
+            window_len: ((METER_WINDOW.as_secs_f64() * sample_rate as f64) as u32).max(1),
+            fill: 0,
+            sum_squares: 0.0,
+            peak: 0.0,
+            clipped: 0,
+        }
+    }
+
+    /// Feeds `samples` and returns the levels of the last window they
+    /// completed, if any.
+    pub fn process(&mut self, samples: &[f32]) -> Option<AudioLevels> {
+        let mut finished = None;
+        for &sample in samples {
+            let magnitude = sample.abs();
+            self.sum_squares += f64::from(sample * sample);
+            self.peak = self.peak.max(magnitude);
+            if magnitude >= CLIP_LEVEL {
+                self.clipped += 1;
+            }
+            self.fill += 1;
+
+            if self.fill == self.window_len {
+                finished = Some(self.take_window());
+            }
+        }
+        finished
+    }
+
+    fn take_window(&mut self) -> AudioLevels {
+        let rms = (self.sum_squares / f64::from(self.fill)).sqrt() as f32;
+        let levels = AudioLevels {
+            rms_dbfs: to_dbfs(rms),
+            peak_dbfs: to_dbfs(self.peak),
+            clipped_samples: self.clipped,
+            clipping: self.clipped as f32 >= CLIP_SHARE * self.fill as f32,
+        };
+        self.fill = 0;
+        self.sum_squares = 0.0;
+        self.peak = 0.0;
+        self.clipped = 0;
+        levels
+    }
+}
+
+fn to_dbfs(level: f32) -> f32 {
+    if level <= 0.0 {
+        return FLOOR_DBFS;
+    }
+    (20.0 * level.log10()).max(FLOOR_DBFS)
+}
+
+/// Thresholds for the silence and clipping alarms.
+#[derive(Debug, Clone, Copy)]
+pub struct LevelAlarmConfig {
+    /// Windows with an RMS level below this count as silent.
+    pub silence_dbfs: f32,
+    /// Minutes of continuous silence before the alarm; 0 disables it.
+    pub silence_minutes: u64,
+    /// Seconds of continuous clipping before the alarm; 0 disables it.
+    pub clipping_secs: u64,
+    /// Whether alarms are also sent through AppRise / Discord.
+    pub notify: bool,
+}
+
+impl LevelAlarmConfig {
+    fn silence_limit(&self) -> Option<Duration> {
+        (self.silence_minutes > 0).then(|| Duration::from_secs(self.silence_minutes * 60))
+    }
+
+    fn clipping_limit(&self) -> Option<Duration> {
+        (self.clipping_secs > 0).then(|| Duration::from_secs(self.clipping_secs))
+    }
+}
+
+pub fn parse_alarm_config(config_json: &serde_json::Value) -> LevelAlarmConfig {
+    LevelAlarmConfig {
+        silence_dbfs: config_json
+            .get("SILENCE_ALARM_DBFS")
+            .and_then(|v| v.as_f64())
+            .unwrap_or(-60.0) as f32,
+        silence_minutes: config_json
+            .get("SILENCE_ALARM_MINUTES")
+            .and_then(|v| v.as_u64())
+            .unwrap_or(5),
+        clipping_secs: config_json
+            .get("CLIPPING_ALARM_SECS")
+            .and_then(|v| v.as_u64())
+            .unwrap_or(30),
+        notify: config_json
+            .get("LEVEL_ALARM_NOTIFY")
+            .and_then(|v| v.as_bool())
+            .unwrap_or(false),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LevelAlarm {
+    Silence,
+    Clipping,
+}
+
+impl LevelAlarm {
+    fn label(&self) -> &'static str {
+        match self {
+            LevelAlarm::Silence => "Silence",
+            LevelAlarm::Clipping => "Clipping",
+        }
+    }
+}
+
+/// One alarm's condition: when it started and whether it has been raised.
+#[derive(Debug, Default)]
+struct AlarmState {
+    since: Option<DateTime<Utc>>,
+    raised: bool,
+}
+
+impl AlarmState {
+    /// Tracks `active` and returns `Some(true)` when the alarm should be
+    /// raised or `Some(false)` when a raised alarm clears.
+    fn update(
+        &mut self,
+        active: bool,
+        limit: Option<Duration>,
+        now: DateTime<Utc>,
+    ) -> Option<bool> {
+        if !active {
+            self.since = None;
+            return std::mem::take(&mut self.raised).then_some(false);
+        }
+        let since = *self.since.get_or_insert(now);
+        let held = (now - since).to_std().unwrap_or_default();
+        match limit {
+            Some(limit) if !self.raised && held >= limit => {
+                self.raised = true;
+                Some(true)
+            }
+            None if self.raised => {
+                self.raised = false;
+                Some(false)
+            }
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct StreamAlarms {
+    silence: AlarmState,
+    clipping: AlarmState,
+}
+
+/// Publishes per-stream levels and raises silence and clipping alarms when a
+/// monitor stays quiet or overdriven for longer than configured.
+pub async fn run_level_monitor(
+    config_handle: ConfigHandle,
+    mut rx: Receiver<LevelReading>,
+    monitoring: MonitoringHub,
+) -> Result<()> {
+    let mut streams: HashMap<String, StreamAlarms> = HashMap::new();
+
+    while let Some(reading) = rx.recv().await {
+        let config = config_handle.current();
+        streams.retain(|stream, _| config.icecast_stream_urls.contains(stream));
+        // Readings still queued from a stream that was just removed.
+        if !config.icecast_stream_urls.contains(&reading.stream) {
+            continue;
+        }
+
+        let thresholds = config.level_alarms;
+        let now = Utc::now();
+        let alarms = streams.entry(reading.stream.clone()).or_default();
+        let changes = [
+            (
+                LevelAlarm::Silence,
+                alarms.silence.update(
+                    reading.levels.rms_dbfs < thresholds.silence_dbfs,
+                    thresholds.silence_limit(),
+                    now,
+                ),
+            ),
+            (
+                LevelAlarm::Clipping,
+                alarms
+                    .clipping
+                    .update(reading.levels.clipping, thresholds.clipping_limit(), now),
+            ),
+        ];
+        monitoring.note_levels(
+            &reading.stream,
+            reading.levels,
+            alarms.silence.raised,
+            alarms.clipping.raised,
+        );
+
+        for (alarm, change) in changes {
+            if let Some(raised) = change {
+                report_alarm(&config, &reading.stream, alarm, raised);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn report_alarm(config: &Config, stream: &str, alarm: LevelAlarm, raised: bool) {
+    let thresholds = config.level_alarms;
+    let detail = match alarm {
+        LevelAlarm::Silence => format!(
+            "no audio above {:.0} dBFS for {} minute(s)",
+            thresholds.silence_dbfs, thresholds.silence_minutes
+        ),
+        LevelAlarm::Clipping => {
+            format!("audio clipping for {} second(s)", thresholds.clipping_secs)
+        }
+    };
+    if raised {
+        warn!(stream, alarm = alarm.label(), "Level alarm: {}", detail);
+    } else {
+        info!(stream, alarm = alarm.label(), "Level alarm cleared");
+    }
+    if !thresholds.notify {
+        return;
+    }
+
+    let monitor_number = config
+        .icecast_stream_urls
+        .iter()
+        .position(|url| url == stream)
+        .map(|idx| idx + 1)
+        .unwrap_or(999);
+    let (title, body) = if raised {
+        (
+            format!(
+                "{} alarm on {} monitor {}",
+                alarm.label(),
+                config.eas_relay_name,
+                monitor_number
+            ),
+            format!("Monitor {} ({}): {}.", monitor_number, stream, detail),
+        )
+    } else {
+        (
+            format!(
+                "{} alarm cleared on {} monitor {}",
+                alarm.label(),
+                config.eas_relay_name,
+                monitor_number
+            ),
+            format!("Monitor {} ({}) is back to normal.", monitor_number, stream),
+        )
+    };
+    let config = config.clone();
+    tokio::spawn(async move {
+        let results = send_status_notification(&config, &title, &body).await;
+        if results.iter().any(|result| !result.success) {
+            warn!(
+                alarm = alarm.label(),
+                "Level alarm notification was not fully delivered"
+            );
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: u32 = 48000;
+
+    fn sine(amplitude: f32, secs: u32) -> Vec<f32> {
+        (0..SAMPLE_RATE * secs)
+            .map(|n| {
+                let t = n as f32 / SAMPLE_RATE as f32;
+                amplitude * (2.0 * std::f32::consts::PI * 1000.0 * t).sin()
+            })
+            .collect()
+    }
+
+    fn at(secs: i64) -> DateTime<Utc> {
+        DateTime::from_timestamp(1_700_000_000 + secs, 0).unwrap()
+    }
+
+    #[test]
+    fn measures_a_sine_at_its_known_level() {
+        let mut meter = LevelMeter::new(SAMPLE_RATE);
+        let levels = meter.process(&sine(0.5, 1)).unwrap();
+        // A sine's RMS is its amplitude over sqrt(2): 0.5 is -6.02 dBFS peak
+        // and -9.03 dBFS RMS.
+        assert!((levels.peak_dbfs + 6.02).abs() < 0.01, "{:?}", levels);
+        assert!((levels.rms_dbfs + 9.03).abs() < 0.01, "{:?}", levels);
+        assert_eq!(levels.clipped_samples, 0);
+        assert!(!levels.clipping);
+    }
+
+    #[test]
+    fn reports_windows_only_once_full() {
+        let mut meter = LevelMeter::new(SAMPLE_RATE);
+        let samples = sine(0.5, 1);
+        assert!(meter
+            .process(&samples[..SAMPLE_RATE as usize / 2])
+            .is_none());
+        assert!(meter
+            .process(&samples[SAMPLE_RATE as usize / 2..])
+            .is_some());
+    }
+
+    #[test]
+    fn digital_silence_reads_as_the_floor() {
+        let mut meter = LevelMeter::new(SAMPLE_RATE);
+        let levels = meter.process(&vec![0.0; SAMPLE_RATE as usize]).unwrap();
+        assert_eq!(levels.rms_dbfs, -120.0);
+        assert_eq!(levels.peak_dbfs, -120.0);
+    }
+
+    #[test]
+    fn flags_clipping_windows() {
+        let mut meter = LevelMeter::new(SAMPLE_RATE);
+        let levels = meter.process(&sine(1.2, 1)).unwrap();
+        assert!(levels.clipping);
+        assert!(levels.clipped_samples > 0);
+    }
+
+    #[test]
+    fn alarm_raises_once_then_clears() {
+        let limit = Some(Duration::from_secs(60));
+        let mut alarm = AlarmState::default();
+        assert_eq!(alarm.update(true, limit, at(0)), None);
+        assert_eq!(alarm.update(true, limit, at(59)), None);
+        assert_eq!(alarm.update(true, limit, at(60)), Some(true));
+        assert_eq!(alarm.update(true, limit, at(61)), None);
+        assert_eq!(alarm.update(true, limit, at(600)), None);
+        assert_eq!(alarm.update(false, limit, at(601)), Some(false));
+        assert_eq!(alarm.update(false, limit, at(602)), None);
+    }
+
+    #[test]
+    fn alarm_restarts_its_timer_after_a_break() {
+        let limit = Some(Duration::from_secs(60));
+        let mut alarm = AlarmState::default();
+        assert_eq!(alarm.update(true, limit, at(0)), None);
+        assert_eq!(alarm.update(false, limit, at(50)), None);
+        assert_eq!(alarm.update(true, limit, at(51)), None);
+        assert_eq!(alarm.update(true, limit, at(100)), None);
+        assert_eq!(alarm.update(true, limit, at(111)), Some(true));
+    }
+
+    #[test]
+    fn disabled_limit_never_raises() {
+        let mut alarm = AlarmState::default();
+        assert_eq!(alarm.update(true, None, at(0)), None);
+        assert_eq!(alarm.update(true, None, at(86_400)), None);
+        assert_eq!(alarm.update(false, None, at(86_401)), None);
+    }
+
+    #[test]
+    fn disabling_a_raised_alarm_clears_it() {
+        let limit = Some(Duration::from_secs(1));
+        let mut alarm = AlarmState::default();
+        assert_eq!(alarm.update(true, limit, at(0)), None);
+        assert_eq!(alarm.update(true, limit, at(1)), Some(true));
+        assert_eq!(alarm.update(true, None, at(2)), Some(false));
+        assert_eq!(alarm.update(true, None, at(3)), None);
+    }
+
+    #[test]
+    fn zero_in_config_disables_an_alarm() {
+        let config = parse_alarm_config(&serde_json::json!({
+            "SILENCE_ALARM_MINUTES": 0,
+            "CLIPPING_ALARM_SECS": 5
+        }));
+        assert_eq!(config.silence_limit(), None);
+        assert_eq!(config.clipping_limit(), Some(Duration::from_secs(5)));
+        assert_eq!(
+            parse_alarm_config(&serde_json::json!({})).silence_limit(),
+            Some(Duration::from_secs(300))
+        );
+    }
+}
@@ -0,0 +1,22 @@
+//! SAME header parsing, message generation and offline decoding, shared by
+//! the `asmara_rust` binary and usable on their own, e.g. to build and check
+//! test fixtures:
+//!
+//! ```no_run
+//! use asmara_rust::decode::decode_file;
+//! use asmara_rust::encode::{write_same_message, EncodeOptions};
+//! use asmara_rust::header::SameHeader;
+//! use std::path::Path;
+//!
+//! let header = SameHeader::parse("ZCZC-EAS-RWT-012057+0100-0241500-WXYZ/FM -")?;
+//! write_same_message(Path::new("rwt.wav"), &header, &EncodeOptions::default())?;
+//! decode_file(Path::new("rwt.wav"), |message, offset| {
+//!     println!("{:?} at sample {}", message, offset);
+//! })?;
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+
+pub mod decode;
+pub mod encode;
+pub mod header;
+pub mod media;
@@ -1,35 +1,57 @@
 use anyhow::Result;
-use monitoring::{MonitoringHub, MonitoringLayer};
-use recording::RecordingState;
+use header::SameHeader;
+use history::AlertHistory;
+use monitoring::{MonitoringHub, MonitoringLayer, StartupLogBuffer};
+use recording::RecordingRegistry;
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::sync::{broadcast, mpsc, Mutex};
-use tracing::info;
 use tracing::level_filters::LevelFilter;
+use tracing::{error, info};
 use tracing_subscriber::filter as other_filter;
 use tracing_subscriber::fmt::time::ChronoLocal;
 use tracing_subscriber::prelude::*;
 use tracing_subscriber::EnvFilter;
 
 mod alerts;
+mod attention;
 mod audio;
 mod backend;
 mod cleanup;
+mod cli;
 mod config;
+mod eas_text;
 mod filter;
-mod header;
+mod fips;
+mod history;
+mod icecast;
+mod levels;
 mod monitoring;
 mod recording;
 mod relay;
+mod relay_hold;
+mod relay_queue;
 mod state;
 mod webhook;
 
-use config::Config;
+use asmara_rust::{decode, encode, header, media};
+use config::{Config, ConfigHandle};
 use state::AppState;
 
+const CONFIG_PATH: &str = "/app/config.json";
+
 #[tokio::main]
 async fn main() -> Result<()> {
-    let config = Config::from_config_json("/app/config.json")?;
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if cli::run(&args)? {
+        return Ok(());
+    }
+
+    // Logging is configured from the config file, so whatever parsing it logs
+    // is held back until logging is set up.
+    let startup_logs = StartupLogBuffer::default();
+    let config_handle = startup_logs.capture(|| ConfigHandle::load(CONFIG_PATH))?;
+    let config = config_handle.current();
 
     let monitoring = MonitoringHub::new(
         config.monitoring_max_log_entries,
@@ -68,45 +90,86 @@ async fn main() -> Result<()> {
         .init();
 
     info!("Starting EAS Listener...");
+    startup_logs.replay();
 
-    let app_state = Arc::new(Mutex::new(AppState::new(config.filters.clone())));
-    let recording_state = Arc::new(Mutex::new(Option::<RecordingState>::None));
+    let app_state = Arc::new(Mutex::new(AppState::new()));
+    if let Err(e) = alerts::restore_active_alerts(&config, &app_state, &monitoring).await {
+        error!("Failed to restore active alerts: {}", e);
+    }
+    let recordings = RecordingRegistry::new();
+    let taps = audio::StreamTaps::new();
+    let (continuous_relay, relay_requests) = relay::ContinuousRelay::new();
+    let relay_queue = relay_queue::RelayQueue::new(monitoring.clone(), continuous_relay);
+    let relay_holds = relay_hold::RelayHolds::new(monitoring.clone());
+    let history = AlertHistory::open(&config.shared_state_dir)?;
 
-    let (tx, rx) = mpsc::channel::<(String, String, String, String, Duration, String)>(32);
-    let (nnnn_tx, _nnnn_rx) = broadcast::channel::<()>(1);
+    let (tx, rx) = mpsc::channel::<(SameHeader, String)>(32);
+    let (nnnn_tx, _nnnn_rx) = broadcast::channel::<audio::EndOfMessage>(16);
+    let (attention_tx, attention_rx) = mpsc::channel::<attention::AttentionDetection>(32);
+    let (levels_tx, levels_rx) = mpsc::channel::<levels::LevelReading>(64);
 
     let audio_processor_handle = tokio::spawn(audio::run_audio_processor(
-        config.clone(),
+        config_handle.clone(),
         tx,
-        recording_state.clone(),
+        recordings.clone(),
+        taps.clone(),
         nnnn_tx.clone(),
+        attention_tx,
+        levels_tx,
         monitoring.clone(),
     ));
     let alert_manager_handle = tokio::spawn(alerts::run_alert_manager(
-        config.clone(),
+        config_handle.clone(),
         app_state.clone(),
         rx,
-        recording_state,
+        recordings,
         nnnn_tx.subscribe(),
         monitoring.clone(),
+        history.clone(),
+        relay_queue,
+        relay_holds.clone(),
+    ));
+    let attention_monitor_handle = tokio::spawn(alerts::run_attention_monitor(
+        config_handle.clone(),
+        app_state.clone(),
+        attention_rx,
+        monitoring.clone(),
+    ));
+    let level_monitor_handle = tokio::spawn(levels::run_level_monitor(
+        config_handle.clone(),
+        levels_rx,
+        monitoring.clone(),
     ));
     let state_cleanup_handle = tokio::spawn(alerts::run_state_cleanup(
-        config.clone(),
+        config_handle.clone(),
         app_state.clone(),
         monitoring.clone(),
     ));
-    let log_cleanup_handle = tokio::spawn(cleanup::run_log_cleanup(config.clone()));
+    let log_cleanup_handle = tokio::spawn(cleanup::run_log_cleanup(config_handle.clone()));
+    let continuous_relay_handle = tokio::spawn(relay::run_continuous_relay(
+        config_handle.clone(),
+        taps,
+        relay_requests,
+    ));
+    let config_watcher_handle = tokio::spawn(config::run_config_watcher(config_handle.clone()));
     let api_handle = tokio::spawn(backend::run_server(
         config.monitoring_bind_addr,
+        config_handle,
         app_state.clone(),
         monitoring,
+        history,
+        relay_holds,
     ));
 
     tokio::select! {
         _ = audio_processor_handle => info!("Audio processor task exited."),
         _ = alert_manager_handle => info!("Alert manager task exited."),
+        _ = attention_monitor_handle => info!("Attention monitor task exited."),
+        _ = level_monitor_handle => info!("Level monitor task exited."),
         _ = state_cleanup_handle => info!("State cleanup task exited."),
         _ = log_cleanup_handle => info!("Log cleanup task exited."),
+        _ = continuous_relay_handle => info!("Continuous relay task exited."),
+        _ = config_watcher_handle => info!("Config watcher task exited."),
         _ = api_handle => info!("Monitoring API task exited."),
     };
 
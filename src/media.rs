@@ -0,0 +1,218 @@
+//! Audio decoding through symphonia, downmixed to mono and resampled with
+//! rubato. Used for monitored streams as well as local files.
+
+use anyhow::{anyhow, Context, Result};
+use rubato::{Resampler, SincFixedIn};
+use std::fs::File;
+use std::path::Path;
+use symphonia::core::audio::SampleBuffer;
+use symphonia::core::codecs::DecoderOptions;
+use symphonia::core::errors::Error as SymphoniaError;
+use symphonia::core::formats::FormatOptions;
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::MetadataOptions;
+use symphonia::core::probe::Hint;
+use tracing::{error, info, warn};
+
+/// Rate that monitored streams and decoded files are resampled to.
+pub const TARGET_SAMPLE_RATE: u32 = 48000;
+const CHUNK_SIZE: usize = 2048;
+
+/// Resamples audio that arrives in arbitrarily sized chunks.
+pub struct ChunkResampler {
+    resampler: Option<SincFixedIn<f32>>,
+    pending: Vec<f32>,
+}
+
+impl ChunkResampler {
+    pub fn new(input_rate: u32, output_rate: u32) -> Result<Self> {
+        let resampler = if input_rate == output_rate {
+            None
+        } else {
+            Some(build_resampler(input_rate, output_rate)?)
+        };
+        Ok(Self {
+            resampler,
+            pending: Vec::new(),
+        })
+    }
+
+    pub fn process(&mut self, input: &[f32], out: &mut Vec<f32>) -> Result<()> {
+        let Some(rs) = self.resampler.as_mut() else {
+            out.extend_from_slice(input);
+            return Ok(());
+        };
+        self.pending.extend_from_slice(input);
+        while self.pending.len() >= CHUNK_SIZE {
+            let resampled = rs.process(&[&self.pending[..CHUNK_SIZE]], None)?;
+            out.extend_from_slice(&resampled[0]);
+            self.pending.drain(..CHUNK_SIZE);
+        }
+        Ok(())
+    }
+
+    /// Resamples whatever is still pending, for input that has ended.
+    pub fn flush(&mut self, out: &mut Vec<f32>) -> Result<()> {
+        if let Some(rs) = self.resampler.as_mut() {
+            if !self.pending.is_empty() {
+                let resampled = rs.process_partial(Some(&[&self.pending]), None)?;
+                out.extend_from_slice(&resampled[0]);
+                self.pending.clear();
+            }
+        }
+        Ok(())
+    }
+}
+
+pub fn decode_file_samples(path: &Path, sample_rate: u32) -> Result<Vec<f32>> {
+    let file = File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let mss = MediaSourceStream::new(Box::new(file), Default::default());
+
+    let mut hint = Hint::new();
+    if let Some(extension) = path.extension().and_then(|ext| ext.to_str()) {
+        hint.with_extension(extension);
+    }
+
+    let label = path.display().to_string();
+    let mut samples = Vec::new();
+    decode_media(mss, &hint, &label, sample_rate, |chunk| {
+        samples.extend_from_slice(&chunk);
+        Ok(())
+    })?;
+
+    Ok(samples)
+}
+
+pub fn decode_media<F>(
+    mss: MediaSourceStream,
+    hint: &Hint,
+    stream_label: &str,
+    output_rate: u32,
+    mut on_chunk: F,
+) -> Result<()>
+where
+    F: FnMut(Vec<f32>) -> Result<()>,
+{
+    let fmt_opts = FormatOptions {
+        enable_gapless: true,
+        ..Default::default()
+    };
+    let probed = symphonia::default::get_probe()
+        .format(hint, mss, &fmt_opts, &MetadataOptions::default())
+        .context("Unsupported format")?;
+    let mut format = probed.format;
+
+    let track = format
+        .default_track()
+        .ok_or_else(|| anyhow!("No default track found"))?;
+    let mut track_id = track.id;
+    let mut decoder = symphonia::default::get_codecs()
+        .make(&track.codec_params, &DecoderOptions::default())
+        .context("Failed to make decoder")?;
+
+    let mut resampler: Option<SincFixedIn<f32>> = None;
+    let mut current_input_rate: Option<u32> = None;
+    let mut audio_buffer: Vec<f32> = Vec::new();
+
+    loop {
+        let packet = match format.next_packet() {
+            Ok(pkt) => pkt,
+            Err(SymphoniaError::ResetRequired) => {
+                if let Some(new_track) = format.default_track() {
+                    track_id = new_track.id;
+                    decoder = symphonia::default::get_codecs()
+                        .make(&new_track.codec_params, &DecoderOptions::default())
+                        .context("Failed to rebuild decoder after ResetRequired")?;
+                }
+                current_input_rate = None;
+                resampler = None;
+                audio_buffer.clear();
+                continue;
+            }
+            Err(SymphoniaError::IoError(_)) => break,
+            Err(e) => {
+                error!(stream = %stream_label, "Packet error: {}", e);
+                break;
+            }
+        };
+
+        if packet.track_id() != track_id {
+            continue;
+        }
+
+        match decoder.decode(&packet) {
+            Ok(decoded) => {
+                if decoded.frames() == 0 {
+                    continue;
+                }
+                let spec = *decoded.spec();
+
+                if current_input_rate != Some(spec.rate) {
+                    current_input_rate = Some(spec.rate);
+                    if spec.rate != output_rate {
+                        info!(
+                            stream = %stream_label,
+                            "Stream detected with sample rate {}. Resampling to {}.",
+                            spec.rate,
+                            output_rate
+                        );
+                    }
+                    resampler = Some(build_resampler(spec.rate, output_rate)?);
+                }
+                let rs = resampler
+                    .as_mut()
+                    .expect("resampler must be initialized when decoding begins");
+
+                let mut mono_samples = vec![0.0f32; decoded.frames()];
+                let mut sample_buf = SampleBuffer::<f32>::new(decoded.frames() as u64, spec);
+                sample_buf.copy_interleaved_ref(decoded);
+                for (i, frame) in sample_buf
+                    .samples()
+                    .chunks_exact(spec.channels.count())
+                    .enumerate()
+                {
+                    mono_samples[i] = frame.iter().sum::<f32>() / frame.len() as f32;
+                }
+                audio_buffer.extend_from_slice(&mono_samples);
+
+                while audio_buffer.len() >= CHUNK_SIZE {
+                    let chunk_to_process = audio_buffer[..CHUNK_SIZE].to_vec();
+                    let mut resampled = rs.process(&[chunk_to_process], None)?;
+                    on_chunk(resampled.swap_remove(0))?;
+                    audio_buffer.drain(..CHUNK_SIZE);
+                }
+            }
+            Err(e) => {
+                warn!(stream = %stream_label, "Decode error: {}", e);
+            }
+        }
+    }
+
+    if let Some(rs) = resampler.as_mut() {
+        if !audio_buffer.is_empty() {
+            let mut resampled = rs.process_partial(Some(&[audio_buffer]), None)?;
+            on_chunk(resampled.swap_remove(0))?;
+        }
+    }
+
+    Ok(())
+}
+
+fn build_resampler(input_rate: u32, output_rate: u32) -> Result<SincFixedIn<f32>> {
+    use rubato::{SincInterpolationParameters, SincInterpolationType, WindowFunction};
+
+    SincFixedIn::new(
+        output_rate as f64 / input_rate as f64,
+        2.0,
+        SincInterpolationParameters {
+            sinc_len: 256,
+            f_cutoff: 0.95,
+            interpolation: SincInterpolationType::Linear,
+            oversampling_factor: 256,
+            window: WindowFunction::BlackmanHarris2,
+        },
+        CHUNK_SIZE,
+        1, // mono
+    )
+    .context("failed to create resampler")
+}
@@ -1,3 +1,5 @@
+use crate::attention::AttentionDetection;
+use crate::levels::AudioLevels;
 use crate::state::ActiveAlert;
 use chrono::{DateTime, Utc};
 use parking_lot::RwLock;
@@ -14,9 +16,13 @@ use tokio::sync::broadcast::{self, Receiver, Sender};
 use tracing::field::{Field, Visit};
 use tracing::{Event, Level, Subscriber};
 use tracing_subscriber::layer::Context;
+use tracing_subscriber::prelude::*;
 use tracing_subscriber::registry::LookupSpan;
 use tracing_subscriber::Layer;
 
+/// Attention signals kept for `/api/status` and new WebSocket clients.
+const MAX_ATTENTION_EVENTS: usize = 50;
+
 #[derive(Debug, Clone, Serialize)]
 pub struct LogEntry {
     pub id: u64,
@@ -42,6 +48,50 @@ pub struct StreamStatusPayload {
     pub last_disconnect: Option<DateTime<Utc>>,
     pub last_error: Option<String>,
     pub uptime_seconds: Option<i64>,
+    /// Levels of the most recent meter window; `None` until audio decodes.
+    pub levels: Option<AudioLevels>,
+    pub silence_alarm: bool,
+    pub clipping_alarm: bool,
+}
+
+/// One destination's relay queue: what is on the air and what is waiting, in
+/// play order.
+#[derive(Debug, Clone, Serialize)]
+pub struct RelayQueuePayload {
+    pub destination: String,
+    pub playing: Option<QueuedRelayPayload>,
+    pub pending: Vec<QueuedRelayPayload>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct QueuedRelayPayload {
+    pub id: u64,
+    pub header: String,
+    pub event_code: String,
+    pub event_text: String,
+    pub priority: String,
+    #[serde(with = "chrono::serde::ts_seconds")]
+    pub queued_at: DateTime<Utc>,
+    #[serde(with = "chrono::serde::ts_seconds_option")]
+    pub started_at: Option<DateTime<Utc>>,
+    pub preemptions: u32,
+}
+
+/// A relay waiting on operator approval.
+#[derive(Debug, Clone, Serialize)]
+pub struct PendingRelayPayload {
+    pub id: u64,
+    pub destination: String,
+    pub header: String,
+    pub event_code: String,
+    pub event_text: String,
+    pub rule: Option<String>,
+    pub hold_secs: u64,
+    pub default_action: String,
+    #[serde(with = "chrono::serde::ts_seconds")]
+    pub held_at: DateTime<Utc>,
+    #[serde(with = "chrono::serde::ts_seconds")]
+    pub expires_at: DateTime<Utc>,
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -50,6 +100,9 @@ pub enum MonitoringEvent {
     Log(LogEntry),
     Stream(StreamStatusPayload),
     Alerts(Vec<ActiveAlert>),
+    RelayQueue(RelayQueuePayload),
+    PendingRelays(Vec<PendingRelayPayload>),
+    Attention(AttentionDetection),
 }
 
 struct StreamTelemetry {
@@ -60,6 +113,9 @@ struct StreamTelemetry {
     last_disconnect: Option<DateTime<Utc>>,
     last_error: Option<String>,
     attempts: u64,
+    levels: Option<AudioLevels>,
+    silence_alarm: bool,
+    clipping_alarm: bool,
 }
 
 impl StreamTelemetry {
@@ -72,6 +128,9 @@ impl StreamTelemetry {
             last_disconnect: None,
             last_error: None,
             attempts: 0,
+            levels: None,
+            silence_alarm: false,
+            clipping_alarm: false,
         }
     }
 }
@@ -79,6 +138,9 @@ impl StreamTelemetry {
 struct MonitoringState {
     logs: VecDeque<LogEntry>,
     streams: HashMap<String, StreamTelemetry>,
+    relay_queues: HashMap<String, RelayQueuePayload>,
+    pending_relays: Vec<PendingRelayPayload>,
+    attention: VecDeque<AttentionDetection>,
 }
 
 impl MonitoringState {
@@ -86,6 +148,9 @@ impl MonitoringState {
         Self {
             logs: VecDeque::new(),
             streams: HashMap::new(),
+            relay_queues: HashMap::new(),
+            pending_relays: Vec::new(),
+            attention: VecDeque::new(),
         }
     }
 }
@@ -155,6 +220,7 @@ impl MonitoringHub {
             state.connected_since = None;
             state.last_activity = None;
             state.last_error = None;
+            state.levels = None;
         });
     }
 
@@ -176,6 +242,20 @@ impl MonitoringHub {
         });
     }
 
+    pub fn note_levels(
+        &self,
+        stream: &str,
+        levels: AudioLevels,
+        silence_alarm: bool,
+        clipping_alarm: bool,
+    ) {
+        self.update_stream(stream, |state| {
+            state.levels = Some(levels);
+            state.silence_alarm = silence_alarm;
+            state.clipping_alarm = clipping_alarm;
+        });
+    }
+
     pub fn note_error(&self, stream: &str, error: String) {
         self.update_stream(stream, move |state| {
             state.is_connected = false;
@@ -194,6 +274,48 @@ impl MonitoringHub {
         });
     }
 
+    pub fn update_relay_queue(&self, queue: RelayQueuePayload) {
+        self.inner
+            .write()
+            .relay_queues
+            .insert(queue.destination.clone(), queue.clone());
+        let _ = self.events_tx.send(MonitoringEvent::RelayQueue(queue));
+    }
+
+    pub fn relay_queue_snapshots(&self) -> Vec<RelayQueuePayload> {
+        let guard = self.inner.read();
+        let mut queues: Vec<_> = guard.relay_queues.values().cloned().collect();
+        queues.sort_by(|a, b| a.destination.cmp(&b.destination));
+        queues
+    }
+
+    pub fn update_pending_relays(&self, pending: Vec<PendingRelayPayload>) {
+        self.inner.write().pending_relays = pending.clone();
+        let _ = self.events_tx.send(MonitoringEvent::PendingRelays(pending));
+    }
+
+    pub fn pending_relays(&self) -> Vec<PendingRelayPayload> {
+        self.inner.read().pending_relays.clone()
+    }
+
+    pub fn record_attention(&self, detection: AttentionDetection) {
+        {
+            let mut guard = self.inner.write();
+            guard.attention.push_front(detection.clone());
+            guard.attention.truncate(MAX_ATTENTION_EVENTS);
+        }
+        let _ = self.events_tx.send(MonitoringEvent::Attention(detection));
+    }
+
+    /// Recent attention signals, newest first.
+    pub fn attention_events(&self) -> Vec<AttentionDetection> {
+        self.inner.read().attention.iter().cloned().collect()
+    }
+
+    pub fn remove_stream(&self, stream: &str) {
+        self.inner.write().streams.remove(stream);
+    }
+
     pub fn recent_logs(&self, count: usize) -> Vec<LogEntry> {
         let guard = self.inner.read();
         guard.logs.iter().rev().take(count).cloned().collect()
@@ -263,6 +385,9 @@ impl MonitoringHub {
             last_disconnect: state.last_disconnect,
             last_error: state.last_error.clone(),
             uptime_seconds,
+            levels: state.levels,
+            silence_alarm: state.silence_alarm,
+            clipping_alarm: state.clipping_alarm,
         }
     }
 }
@@ -347,3 +472,56 @@ where
         );
     }
 }
+
+/// Level, target and formatted message of a held-back event.
+type BufferedEvent = (Level, &'static str, String);
+
+/// Keeps the events logged before logging is set up, such as warnings from
+/// parsing the config that logging is configured from, and replays them once
+/// it is.
+#[derive(Clone, Default)]
+pub struct StartupLogBuffer {
+    events: Arc<parking_lot::Mutex<Vec<BufferedEvent>>>,
+}
+
+impl StartupLogBuffer {
+    /// Runs `f` with the events it logs held back.
+    pub fn capture<T>(&self, f: impl FnOnce() -> T) -> T {
+        let subscriber = tracing_subscriber::registry().with(self.clone());
+        tracing::subscriber::with_default(subscriber, f)
+    }
+
+    /// Logs the held-back events through the global subscriber.
+    pub fn replay(self) {
+        for (level, source, message) in self.events.lock().drain(..) {
+            match level {
+                Level::ERROR => tracing::error!(source, "{}", message),
+                Level::WARN => tracing::warn!(source, "{}", message),
+                Level::INFO => tracing::info!(source, "{}", message),
+                _ => tracing::debug!(source, "{}", message),
+            }
+        }
+    }
+}
+
+impl<S> Layer<S> for StartupLogBuffer
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+{
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let mut visitor = LogVisitor::default();
+        event.record(&mut visitor);
+        let (mut message, fields) = visitor.finish();
+        for (name, value) in fields {
+            match value {
+                Value::String(value) => message.push_str(&format!(" {}={}", name, value)),
+                value => message.push_str(&format!(" {}={}", name, value)),
+            }
+        }
+        self.events.lock().push((
+            *event.metadata().level(),
+            event.metadata().target(),
+            message,
+        ));
+    }
+}
@@ -1,44 +1,110 @@
+use crate::audio::TARGET_SAMPLE_RATE;
 use crate::config::Config;
-use crate::header;
-use anyhow::Result;
+use crate::header::{self, SameHeader};
+use anyhow::{anyhow, Context, Result};
 use chrono::Local;
-use hound::{WavSpec, WavWriter};
-use std::path::PathBuf;
+use hound::{WavReader, WavSpec, WavWriter};
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, ErrorKind, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
 use tokio::sync::mpsc;
 use tracing::info;
 
-const TARGET_SAMPLE_RATE: u32 = 48000;
 const HEADER_AMPLITUDE: f64 = 0.79;
 
+static NEXT_RECORDING_ID: AtomicU64 = AtomicU64::new(1);
+
 #[derive(Debug, Clone)]
 pub struct RecordingState {
+    pub id: u64,
     pub audio_tx: mpsc::Sender<Vec<f32>>,
     pub output_path: PathBuf,
     pub source_stream: String,
+    /// Markers written into the file as WAV cue points when it is finished.
+    pub markers: Arc<Mutex<Vec<RecordingMarker>>>,
+}
+
+/// A labelled region of a recording, counted in samples of recorded audio
+/// (after the generated header).
+#[derive(Debug, Clone)]
+pub struct RecordingMarker {
+    pub position: u64,
+    pub length: u64,
+    pub label: String,
+}
+
+impl RecordingState {
+    /// Closes the audio channel, which lets the encoder finalize the file.
+    pub fn finish(self) -> FinishedRecording {
+        FinishedRecording {
+            path: self.output_path,
+        }
+    }
+}
+
+/// A recording whose audio channel has been closed.
+#[derive(Debug, Clone)]
+pub struct FinishedRecording {
+    pub path: PathBuf,
+}
+
+/// Recordings currently in progress, keyed by recording id. Each alert gets its
+/// own entry, so overlapping alerts on the same or different streams are all
+/// captured.
+#[derive(Debug, Clone, Default)]
+pub struct RecordingRegistry {
+    recordings: Arc<Mutex<HashMap<u64, RecordingState>>>,
+}
+
+impl RecordingRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&self, state: RecordingState) {
+        self.recordings.lock().insert(state.id, state);
+    }
+
+    pub fn remove(&self, id: u64) -> Option<RecordingState> {
+        self.recordings.lock().remove(&id)
+    }
+
+    pub fn for_stream(&self, source_stream: &str) -> Vec<RecordingState> {
+        self.recordings
+            .lock()
+            .values()
+            .filter(|state| state.source_stream == source_stream)
+            .cloned()
+            .collect()
+    }
 }
 
 pub fn start_encoding_task(
     config: &Config,
-    header_text: &str,
+    header: &SameHeader,
     source_stream: &str,
 ) -> Result<(tokio::task::JoinHandle<Result<()>>, RecordingState)> {
     std::fs::create_dir_all(&config.recording_dir)?;
-    let filename = format!(
-        "EAS_Recording_{}.wav",
-        Local::now().format("%Y-%m-%d_%H-%M-%S")
-    );
-    let output_path = config.recording_dir.join(filename);
+    let (output_path, file) = create_recording_file(&config.recording_dir)?;
     let output_path_clone = output_path.clone();
 
-    let header_samples =
-        header::generate_same_header_samples(header_text, TARGET_SAMPLE_RATE, HEADER_AMPLITUDE)?;
+    let header_samples = header::generate_same_header_samples(
+        &header.to_string(),
+        TARGET_SAMPLE_RATE,
+        HEADER_AMPLITUDE,
+    )?;
     let header_sample_count = header_samples.len();
 
     let nnnn_samples =
         header::generate_same_header_samples("NNNN", TARGET_SAMPLE_RATE, HEADER_AMPLITUDE)?;
-    let nnnn_sample_count = nnnn_samples.len();
 
     let (audio_tx, audio_rx) = mpsc::channel::<Vec<f32>>(32);
+    let markers = Arc::new(Mutex::new(Vec::new()));
+    let markers_for_file = markers.clone();
 
     let handle = tokio::spawn(async move {
         let spec = WavSpec {
@@ -48,48 +114,171 @@ pub fn start_encoding_task(
             sample_format: hound::SampleFormat::Int,
         };
 
-        let writer = WavWriter::create(&output_path, spec)?;
+        let writer = WavWriter::new(BufWriter::new(file), spec)?;
 
-        let samples_written = tokio::task::spawn_blocking(move || {
+        tokio::task::spawn_blocking(move || {
             let mut blocking_writer = writer;
             let mut audio_rx = audio_rx;
             for &sample in &header_samples {
                 blocking_writer.write_sample(sample)?;
             }
 
-            let mut samples_written = header_sample_count;
             let amplitude = i16::MAX as f32;
             while let Some(samples) = audio_rx.blocking_recv() {
                 for sample in samples {
                     blocking_writer.write_sample((sample * amplitude) as i16)?;
-                    samples_written += 1;
                 }
             }
 
             for &sample in &nnnn_samples {
                 blocking_writer.write_sample(sample)?;
             }
-
-            samples_written += nnnn_sample_count;
             blocking_writer.finalize()?;
-            Ok::<_, anyhow::Error>(samples_written)
+            Ok::<_, anyhow::Error>(())
         })
         .await??;
 
-        if samples_written == 0 {
-            let _ = tokio::fs::remove_file(&output_path).await;
-            info!("Deleted empty recording file: {:?}", output_path);
-        } else {
-            info!("Finished writing recording to: {:?}", output_path);
+        let markers = std::mem::take(&mut *markers_for_file.lock());
+        if !markers.is_empty() {
+            let path = output_path.clone();
+            let offset = header_sample_count as u64;
+            tokio::task::spawn_blocking(move || write_cue_markers(&path, &markers, offset))
+                .await??;
         }
+        info!("Finished writing recording to: {:?}", output_path);
 
         Ok(())
     });
 
     let state = RecordingState {
+        id: NEXT_RECORDING_ID.fetch_add(1, Ordering::Relaxed),
         audio_tx,
         output_path: output_path_clone,
         source_stream: source_stream.to_string(),
+        markers,
     };
     Ok((handle, state))
 }
+
+/// Reads a finished recording with the SAME header it opens with replaced by
+/// `forwarded`. The file itself is not touched. Returns the samples and
+/// their sample rate.
+pub fn forwarded_recording(
+    recording: &FinishedRecording,
+    original: &SameHeader,
+    forwarded: &SameHeader,
+) -> Result<(Vec<f32>, u32)> {
+    let path = &recording.path;
+    let mut reader = WavReader::open(path)
+        .with_context(|| format!("Failed to open recording {}", path.display()))?;
+    let sample_rate = reader.spec().sample_rate;
+    let samples = reader
+        .samples::<i16>()
+        .collect::<Result<Vec<i16>, _>>()
+        .with_context(|| format!("Failed to read recording {}", path.display()))?;
+
+    let original_len =
+        header::generate_same_header_samples(&original.to_string(), sample_rate, HEADER_AMPLITUDE)?
+            .len();
+    let body = samples.get(original_len..).ok_or_else(|| {
+        anyhow!(
+            "Recording {} is shorter than its SAME header",
+            path.display()
+        )
+    })?;
+    let forwarded_header = header::generate_same_header_samples(
+        &forwarded.to_string(),
+        sample_rate,
+        HEADER_AMPLITUDE,
+    )?;
+
+    let scale = i16::MAX as f32;
+    let samples = forwarded_header
+        .iter()
+        .chain(body)
+        .map(|&sample| sample as f32 / scale)
+        .collect();
+    Ok((samples, sample_rate))
+}
+
+/// Appends `markers` to a finished WAV file as cue points with labels
+/// (`cue ` and `LIST`/`adtl` chunks), which most audio editors show as
+/// markers or regions. `offset` is where the recorded audio begins.
+fn write_cue_markers(path: &Path, markers: &[RecordingMarker], offset: u64) -> Result<()> {
+    let position =
+        |marker: &RecordingMarker| (marker.position + offset).min(u32::MAX as u64) as u32;
+
+    let mut cue = Vec::new();
+    cue.extend_from_slice(&(markers.len() as u32).to_le_bytes());
+    for (idx, marker) in markers.iter().enumerate() {
+        let id = idx as u32 + 1;
+        cue.extend_from_slice(&id.to_le_bytes());
+        cue.extend_from_slice(&position(marker).to_le_bytes());
+        cue.extend_from_slice(b"data");
+        cue.extend_from_slice(&0u32.to_le_bytes());
+        cue.extend_from_slice(&0u32.to_le_bytes());
+        cue.extend_from_slice(&position(marker).to_le_bytes());
+    }
+
+    let mut adtl = b"adtl".to_vec();
+    for (idx, marker) in markers.iter().enumerate() {
+        let id = idx as u32 + 1;
+        let mut label = id.to_le_bytes().to_vec();
+        label.extend_from_slice(marker.label.as_bytes());
+        label.push(0);
+        push_chunk(&mut adtl, b"labl", &label);
+
+        let mut region = id.to_le_bytes().to_vec();
+        region.extend_from_slice(&(marker.length.min(u32::MAX as u64) as u32).to_le_bytes());
+        region.extend_from_slice(b"rgn ");
+        region.extend_from_slice(&[0; 8]);
+        push_chunk(&mut adtl, b"ltxt", &region);
+    }
+
+    let mut chunks = Vec::new();
+    push_chunk(&mut chunks, b"cue ", &cue);
+    push_chunk(&mut chunks, b"LIST", &adtl);
+
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(path)
+        .with_context(|| format!("Failed to open {} for markers", path.display()))?;
+    let end = file.seek(SeekFrom::End(0))?;
+    file.write_all(&chunks)?;
+    let riff_size = u32::try_from(end + chunks.len() as u64 - 8)
+        .map_err(|_| anyhow!("Recording {} is too large for markers", path.display()))?;
+    file.seek(SeekFrom::Start(4))?;
+    file.write_all(&riff_size.to_le_bytes())?;
+    Ok(())
+}
+
+/// Appends a RIFF chunk, padded to an even length.
+fn push_chunk(out: &mut Vec<u8>, id: &[u8; 4], body: &[u8]) {
+    out.extend_from_slice(id);
+    out.extend_from_slice(&(body.len() as u32).to_le_bytes());
+    out.extend_from_slice(body);
+    if body.len() % 2 == 1 {
+        out.push(0);
+    }
+}
+
+/// Creates a new recording file, adding a numeric suffix when another
+/// recording already claimed the same second.
+fn create_recording_file(recording_dir: &Path) -> Result<(PathBuf, File)> {
+    let timestamp = Local::now().format("%Y-%m-%d_%H-%M-%S").to_string();
+    let mut suffix = 1;
+    loop {
+        let filename = if suffix == 1 {
+            format!("EAS_Recording_{}.wav", timestamp)
+        } else {
+            format!("EAS_Recording_{}_{}.wav", timestamp, suffix)
+        };
+        let path = recording_dir.join(filename);
+        match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(file) => return Ok((path, file)),
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => suffix += 1,
+            Err(e) => return Err(e.into()),
+        }
+    }
+}
@@ -1,230 +1,1070 @@
-use crate::config::Config;
+use crate::audio::{StreamTaps, TARGET_SAMPLE_RATE};
+use crate::config::{Config, ConfigHandle};
+use crate::eas_text;
 use crate::filter::{self, FilterAction, FilterRule};
+use crate::header::{self, SameHeader};
+use crate::icecast::{self, IcecastSource, IcecastTarget, RelayCodec, StreamFormat, StreamInfo};
+use crate::media::{self, ChunkResampler};
+use crate::recording::{self, FinishedRecording};
+use crate::relay_hold::{HoldVerdict, RelayHolds};
+use crate::relay_queue::{PlayOutcome, RelayQueue};
+use crate::state::ActiveAlert;
 use anyhow::{anyhow, Context, Result};
-use std::path::{Path, PathBuf};
-use tempfile::Builder;
-use tokio::process::Command;
-use tracing::info;
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot, watch};
+use tokio::task::JoinHandle;
+use tokio::time::{interval, sleep, MissedTickBehavior};
+use tracing::{info, warn};
 
-const TARGET_SAMPLE_RATE: u32 = 48_000;
-const TARGET_CHANNEL_LAYOUT: &str = "mono";
+/// Silence between the intro, the alert and the outro.
+const SEGMENT_GAP_SECS: u32 = 1;
+/// How often the continuous relay tops up the mount with program audio.
+const PROGRAM_TICK: Duration = Duration::from_millis(100);
+/// Program audio buffered from a monitor before it is played, to ride out
+/// network jitter on the monitored stream.
+const PROGRAM_PREBUFFER: Duration = Duration::from_secs(1);
+/// Program audio beyond this is dropped so the relay stays close to live.
+const PROGRAM_MAX_BUFFER: Duration = Duration::from_secs(5);
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+/// Name given to the destination built from the single-mount `ICECAST_RELAY`.
+const LEGACY_DESTINATION_NAME: &str = "default";
+pub(crate) const RELAY_DESCRIPTION: &str = "Emergency Alert System relay";
 
+/// Whether the relay mount only exists while an alert plays, or stays up
+/// carrying program audio between alerts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RelayMode {
+    #[default]
+    OnAlert,
+    Continuous,
+}
+
+impl FromStr for RelayMode {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "alert" | "on_alert" | "on-alert" => Ok(RelayMode::OnAlert),
+            "continuous" => Ok(RelayMode::Continuous),
+            other => Err(anyhow!(
+                "Unsupported relay mode '{}'; expected 'alert' or 'continuous'",
+                other
+            )),
+        }
+    }
+}
+
+/// What a continuous relay plays when no alert is on the air.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum ProgramSource {
+    #[default]
+    Silence,
+    /// One of the monitored streams, by URL.
+    Stream(String),
+    /// An audio file played on a loop.
+    File(PathBuf),
+}
+
+impl ProgramSource {
+    /// Parses a relay program: `"silence"`, a 1-based monitor number
+    /// (as a number or `"#2"`), a monitored stream URL, or a filler file path.
+    pub fn parse(value: Option<&Value>, stream_urls: &[String]) -> Result<Self> {
+        let monitor = match value {
+            None | Some(Value::Null) => return Ok(ProgramSource::Silence),
+            Some(Value::Number(number)) => Some(
+                number
+                    .as_u64()
+                    .ok_or_else(|| anyhow!("relay program must be a monitor number"))?,
+            ),
+            Some(Value::String(text)) => text.trim().trim_start_matches('#').parse().ok(),
+            Some(other) => {
+                return Err(anyhow!(
+                    "relay program must be a string or monitor number, got {}",
+                    other
+                ))
+            }
+        };
+
+        if let Some(number) = monitor {
+            return stream_urls
+                .get((number as usize).wrapping_sub(1))
+                .map(|url| ProgramSource::Stream(url.clone()))
+                .ok_or_else(|| {
+                    anyhow!(
+                        "relay program references monitor #{} which does not exist",
+                        number
+                    )
+                });
+        }
+
+        let text = value.and_then(Value::as_str).unwrap_or_default().trim();
+        if text.is_empty() || text.eq_ignore_ascii_case("silence") {
+            return Ok(ProgramSource::Silence);
+        }
+        if stream_urls.iter().any(|url| url == text) {
+            return Ok(ProgramSource::Stream(text.to_string()));
+        }
+        if text.starts_with("http://") || text.starts_with("https://") {
+            return Err(anyhow!(
+                "relay program stream '{}' is not in ICECAST_STREAM_URL_ARRAY",
+                text
+            ));
+        }
+
+        let path = PathBuf::from(text);
+        if !path.is_file() {
+            return Err(anyhow!(
+                "relay program filler file '{}' does not exist",
+                path.display()
+            ));
+        }
+        Ok(ProgramSource::File(path))
+    }
+}
+
+impl fmt::Display for ProgramSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProgramSource::Silence => write!(f, "silence"),
+            ProgramSource::Stream(url) => write!(f, "stream {}", url),
+            ProgramSource::File(path) => write!(f, "file {}", path.display()),
+        }
+    }
+}
+
+/// Regenerates the SAME header of relayed alerts with our own station ID
+/// (`EAS_RELAY_NAME`), as an ENDEC does when it forwards an alert.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HeaderRewrite {
+    pub originator: Option<String>,
+    pub event: Option<String>,
+}
+
+impl HeaderRewrite {
+    /// Reads `rewrite_header`, `originator` and `event_code`. Either override
+    /// turns rewriting on by itself.
+    fn parse(entry: &Value) -> Result<Option<Self>> {
+        let code = |field: &str| {
+            entry
+                .get(field)
+                .and_then(Value::as_str)
+                .map(|code| code.trim().to_ascii_uppercase())
+                .filter(|code| !code.is_empty())
+        };
+        let originator = code("originator");
+        if let Some(originator) = &originator {
+            header::check_originator(originator)?;
+        }
+        let event = code("event_code");
+        if let Some(event) = &event {
+            header::check_event(event)?;
+        }
+
+        let enabled = entry
+            .get("rewrite_header")
+            .and_then(Value::as_bool)
+            .unwrap_or(false);
+        if !enabled && originator.is_none() && event.is_none() {
+            return Ok(None);
+        }
+        Ok(Some(Self { originator, event }))
+    }
+
+    pub fn apply(&self, header: &SameHeader, station_name: &str) -> Result<SameHeader> {
+        header
+            .forwarded(
+                &header::station_id_from_name(station_name),
+                self.originator.as_deref(),
+                self.event.as_deref(),
+            )
+            .context("Failed to rewrite the relayed header")
+    }
+}
+
+/// One Icecast mount alerts are relayed to, with its own format, bumpers and
+/// optionally its own filter rules.
+#[derive(Debug, Clone)]
+pub struct RelayDestination {
+    pub name: String,
+    pub url: String,
+    pub format: StreamFormat,
+    pub intro: PathBuf,
+    pub outro: PathBuf,
+    pub mode: RelayMode,
+    pub program: ProgramSource,
+    /// Replaces the global `FILTERS` for this destination when set.
+    pub filters: Option<Vec<FilterRule>>,
+    pub rewrite: Option<HeaderRewrite>,
+}
+
+impl RelayDestination {
+    fn parse(entry: &Value, stream_urls: &[String], timezone: Tz) -> Result<Self> {
+        let name = entry
+            .get("name")
+            .and_then(Value::as_str)
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .ok_or_else(|| anyhow!("Relay destination is missing a name"))?
+            .to_string();
+        Self::parse_named(&name, entry, stream_urls, timezone)
+            .with_context(|| format!("Relay destination '{}'", name))
+    }
+
+    fn parse_named(
+        name: &str,
+        entry: &Value,
+        stream_urls: &[String],
+        timezone: Tz,
+    ) -> Result<Self> {
+        let url = entry
+            .get("url")
+            .and_then(Value::as_str)
+            .map(str::trim)
+            .filter(|url| !url.is_empty())
+            .ok_or_else(|| anyhow!("url must be set"))?
+            .to_string();
+        IcecastTarget::parse(&url)?;
+
+        let codec: RelayCodec = entry
+            .get("codec")
+            .and_then(Value::as_str)
+            .unwrap_or("pcm16")
+            .parse()?;
+
+        // The stream is uncompressed, so a bitrate just picks the sample rate.
+        let sample_rate = match (
+            entry.get("sample_rate").and_then(Value::as_u64),
+            entry.get("bitrate").and_then(Value::as_u64),
+        ) {
+            (Some(rate), _) => rate,
+            (None, Some(kbps)) => kbps * 1000 / codec.bits_per_sample() as u64,
+            (None, None) => 48000,
+        };
+        if !(8000..=96000).contains(&sample_rate) {
+            return Err(anyhow!(
+                "sample rate must be between 8000 and 96000 (got {})",
+                sample_rate
+            ));
+        }
+
+        let path = |field: &str| {
+            entry
+                .get(field)
+                .and_then(Value::as_str)
+                .map(PathBuf::from)
+                .unwrap_or_default()
+        };
+
+        let mode: RelayMode = entry
+            .get("mode")
+            .and_then(Value::as_str)
+            .unwrap_or("alert")
+            .parse()?;
+
+        let filters = match entry.get("filters") {
+            None | Some(Value::Null) => None,
+            Some(Value::Array(entries)) => {
+                let urls: Vec<&str> = stream_urls.iter().map(String::as_str).collect();
+                Some(filter::parse_filter_rules(entries, &urls, timezone)?)
+            }
+            Some(_) => return Err(anyhow!("filters must be an array")),
+        };
+
+        Ok(Self {
+            name: name.to_string(),
+            url,
+            format: StreamFormat {
+                codec,
+                sample_rate: sample_rate as u32,
+            },
+            intro: path("intro"),
+            outro: path("outro"),
+            mode,
+            program: ProgramSource::parse(entry.get("program"), stream_urls)?,
+            filters,
+            rewrite: HeaderRewrite::parse(entry)?,
+        })
+    }
+}
+
+/// Reads `ICECAST_RELAY_DESTINATIONS`. Without it, the older single-mount
+/// `ICECAST_RELAY*` keys become one destination named `default`.
+pub fn parse_destinations(
+    config_json: &Value,
+    stream_urls: &[String],
+    timezone: Tz,
+) -> Result<Vec<RelayDestination>> {
+    let Some(entries) = config_json.get("ICECAST_RELAY_DESTINATIONS") else {
+        let url = config_json
+            .get("ICECAST_RELAY")
+            .and_then(Value::as_str)
+            .unwrap_or("");
+        if url.trim().is_empty() {
+            return Ok(Vec::new());
+        }
+        let legacy = json!({
+            "url": url,
+            "codec": config_json.get("ICECAST_RELAY_CODEC"),
+            "sample_rate": config_json.get("ICECAST_RELAY_SAMPLE_RATE"),
+            "intro": config_json.get("ICECAST_INTRO"),
+            "outro": config_json.get("ICECAST_OUTRO"),
+            "mode": config_json.get("ICECAST_RELAY_MODE"),
+            "program": config_json.get("ICECAST_RELAY_PROGRAM"),
+            "rewrite_header": config_json.get("ICECAST_RELAY_REWRITE_HEADER"),
+            "originator": config_json.get("ICECAST_RELAY_ORIGINATOR"),
+            "event_code": config_json.get("ICECAST_RELAY_EVENT_CODE"),
+        });
+        return RelayDestination::parse_named(
+            LEGACY_DESTINATION_NAME,
+            &legacy,
+            stream_urls,
+            timezone,
+        )
+        .map(|destination| vec![destination])
+        .context("ICECAST_RELAY");
+    };
+
+    let entries = entries
+        .as_array()
+        .ok_or_else(|| anyhow!("ICECAST_RELAY_DESTINATIONS must be an array"))?;
+    let mut destinations: Vec<RelayDestination> = Vec::new();
+    for entry in entries {
+        let destination = RelayDestination::parse(entry, stream_urls, timezone)?;
+        if destinations
+            .iter()
+            .any(|existing| existing.name == destination.name)
+        {
+            return Err(anyhow!(
+                "Relay destination name '{}' is used more than once",
+                destination.name
+            ));
+        }
+        destinations.push(destination);
+    }
+    Ok(destinations)
+}
+
+/// Hands alert audio to the continuous relay task, which splices it into the
+/// program feed.
+#[derive(Debug, Clone)]
+pub struct ContinuousRelay {
+    requests: mpsc::Sender<OverrideRequest>,
+}
+
+#[derive(Debug)]
+pub struct OverrideRequest {
+    destination: String,
+    samples: Arc<Vec<f32>>,
+    sample_rate: u32,
+    title: String,
+    preempt: watch::Receiver<bool>,
+    done: oneshot::Sender<Result<PlayOutcome>>,
+}
+
+impl ContinuousRelay {
+    pub fn new() -> (Self, mpsc::Receiver<OverrideRequest>) {
+        let (requests, rx) = mpsc::channel(8);
+        (Self { requests }, rx)
+    }
+
+    /// Plays `samples` on the continuous mount, returning once they have been
+    /// handed to the server or `preempt` is raised.
+    pub async fn play(
+        &self,
+        destination: &str,
+        samples: Arc<Vec<f32>>,
+        sample_rate: u32,
+        title: String,
+        preempt: watch::Receiver<bool>,
+    ) -> Result<PlayOutcome> {
+        let (done, result) = oneshot::channel();
+        self.requests
+            .send(OverrideRequest {
+                destination: destination.to_string(),
+                samples,
+                sample_rate,
+                title,
+                preempt,
+                done,
+            })
+            .await
+            .map_err(|_| anyhow!("Continuous relay task is not running"))?;
+        result
+            .await
+            .map_err(|_| anyhow!("Continuous relay task dropped the alert"))?
+    }
+}
+
+/// What `RelayState::start_relay` did with an alert for one destination.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RelayOutcome {
+    Relayed,
+    Filtered,
+    Cancelled(HoldVerdict),
+}
+
+/// Whether an alert went out on a destination.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RelayStatus {
+    Relayed,
+    Skipped,
+    Cancelled,
+    Failed,
+}
+
+impl fmt::Display for RelayStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RelayStatus::Relayed => write!(f, "relayed"),
+            RelayStatus::Skipped => write!(f, "skipped"),
+            RelayStatus::Cancelled => write!(f, "cancelled"),
+            RelayStatus::Failed => write!(f, "failed"),
+        }
+    }
+}
+
+/// The result of relaying an alert to one destination, as stored in the
+/// history and sent in notifications.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RelayResult {
+    pub destination: String,
+    pub status: RelayStatus,
+    pub detail: Option<String>,
+}
+
+impl RelayResult {
+    pub fn from_outcome(destination: &str, outcome: RelayOutcome) -> Self {
+        let (status, detail) = match outcome {
+            RelayOutcome::Relayed => (RelayStatus::Relayed, None),
+            RelayOutcome::Filtered => (RelayStatus::Skipped, Some("filter")),
+            RelayOutcome::Cancelled(HoldVerdict::Expired(_)) => {
+                (RelayStatus::Cancelled, Some("hold expired"))
+            }
+            RelayOutcome::Cancelled(_) => (RelayStatus::Cancelled, Some("operator")),
+        };
+        Self {
+            destination: destination.to_string(),
+            status,
+            detail: detail.map(str::to_string),
+        }
+    }
+
+    pub fn failed(destination: &str, detail: impl Into<String>) -> Self {
+        Self {
+            destination: destination.to_string(),
+            status: RelayStatus::Failed,
+            detail: Some(detail.into()),
+        }
+    }
+}
+
+impl fmt::Display for RelayResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.detail {
+            Some(detail) => write!(f, "{}: {}", self.status, detail),
+            None => write!(f, "{}", self.status),
+        }
+    }
+}
+
+#[derive(Clone)]
 pub struct RelayState {
-    pub config: Config,
+    pub config: Arc<Config>,
+    queue: RelayQueue,
+    holds: RelayHolds,
 }
 
 impl RelayState {
-    pub async fn new(config: Config) -> Result<Self> {
-        if config.should_relay && config.icecast_relay.is_empty() {
-            return Err(anyhow!("ICECAST_RELAY must be set if SHOULD_RELAY is true"));
+    pub async fn new(config: Arc<Config>, queue: RelayQueue, holds: RelayHolds) -> Result<Self> {
+        if config.should_relay && config.relay_destinations.is_empty() {
+            return Err(anyhow!(
+                "ICECAST_RELAY or ICECAST_RELAY_DESTINATIONS must be set if SHOULD_RELAY is true"
+            ));
         }
 
-        Ok(Self { config })
+        Ok(Self {
+            config,
+            queue,
+            holds,
+        })
     }
 
-    pub async fn start_relay<P>(
+    /// Returns `RelayOutcome::Filtered` when the alert's decision does not
+    /// include this destination. A rule with a hold keeps the relay pending
+    /// until an operator decides or the hold runs out.
+    pub async fn start_relay(
         &self,
-        event_code: &str,
-        filters: &[FilterRule],
-        recorded_segment: P,
-        _source_stream: Option<&str>,
-    ) -> Result<()>
-    where
-        P: AsRef<Path>,
-    {
-        let (action, filter_name) = filter::match_filter(filters, event_code)
-            .map(|rule| (rule.action, rule.name.as_str()))
-            .unwrap_or((FilterAction::Relay, "Default Filter"));
-
-        match action {
+        destination: &RelayDestination,
+        alert: &ActiveAlert,
+        recording: &FinishedRecording,
+    ) -> Result<RelayOutcome> {
+        let event_code = alert.header.event.as_str();
+        let Some(decision) = alert.decision.destination(&destination.name) else {
+            info!(
+                event_code,
+                destination = %destination.name,
+                "No relay decision for this destination; skipping relay."
+            );
+            return Ok(RelayOutcome::Filtered);
+        };
+        let filter_name = decision.rule_name();
+
+        match decision.action {
             FilterAction::Ignore => {
                 info!(
                     event_code,
+                    destination = %destination.name,
                     filter = filter_name,
                     "Filter action 'ignore'; skipping relay."
                 );
-                return Ok(());
+                return Ok(RelayOutcome::Filtered);
             }
             FilterAction::Log => {
                 info!(
                     event_code,
+                    destination = %destination.name,
                     filter = filter_name,
                     "Filter action 'log'; recording retained, skipping relay."
                 );
-                return Ok(());
+                return Ok(RelayOutcome::Filtered);
+            }
+            FilterAction::Relay if !decision.relay => {
+                info!(
+                    event_code,
+                    destination = %destination.name,
+                    filter = filter_name,
+                    "Icecast relay not enabled for this alert; skipping relay."
+                );
+                return Ok(RelayOutcome::Filtered);
             }
             FilterAction::Relay => {
                 info!(
                     event_code,
+                    destination = %destination.name,
                     filter = filter_name,
                     "Filter action 'relay'; proceeding with relay."
                 );
             }
         }
 
-        info!("Starting relay to Icecast servers...");
+        if let Some(policy) = decision.hold {
+            let verdict = self
+                .holds
+                .hold(&destination.name, alert, decision.rule.as_deref(), policy)
+                .await;
+            if !verdict.should_relay() {
+                return Ok(RelayOutcome::Cancelled(verdict));
+            }
+        }
 
         let config = &self.config;
-        let recorded_segment = recorded_segment.as_ref();
-
-        if recorded_segment.as_os_str().is_empty() {
+        if recording.path.as_os_str().is_empty() {
             return Err(anyhow!(
                 "Recording segment path is empty. Cannot start relay."
             ));
         }
 
-        if config.icecast_relay.is_empty() {
-            return Err(anyhow!("ICECAST_RELAY is not set. Cannot start relay."));
-        }
+        // The recording stays as received; a rewritten header only goes
+        // into the relay bundle.
+        let (recording, on_air) = match &destination.rewrite {
+            Some(rewrite) => {
+                let forwarded = rewrite.apply(&alert.header, &config.eas_relay_name)?;
+                info!(
+                    destination = %destination.name,
+                    original = %alert.header,
+                    forwarded = %forwarded,
+                    "Relaying with rewritten SAME header"
+                );
+                let segment = BundleSegment::Forwarded {
+                    recording: recording.clone(),
+                    original: Box::new(alert.header.clone()),
+                    forwarded: Box::new(forwarded.clone()),
+                };
+                (segment, forwarded)
+            }
+            None => (
+                BundleSegment::File(recording.path.clone()),
+                alert.header.clone(),
+            ),
+        };
 
         let mut audio_segments = Vec::new();
-
-        if !config.icecast_intro.as_os_str().is_empty() {
-            audio_segments.push(config.icecast_intro.clone());
+        if !destination.intro.as_os_str().is_empty() {
+            audio_segments.push(BundleSegment::File(destination.intro.clone()));
+        }
+        audio_segments.push(recording);
+        if !destination.outro.as_os_str().is_empty() {
+            audio_segments.push(BundleSegment::File(destination.outro.clone()));
         }
 
-        audio_segments.push(recorded_segment.to_path_buf());
+        let sample_rate = destination.format.sample_rate;
+        let samples =
+            tokio::task::spawn_blocking(move || build_bundle(&audio_segments, sample_rate))
+                .await
+                .context("Relay bundle task failed")??;
 
-        if !config.icecast_outro.as_os_str().is_empty() {
-            audio_segments.push(config.icecast_outro.clone());
-        }
+        let info = StreamInfo {
+            name: config.eas_relay_name.clone(),
+            description: RELAY_DESCRIPTION.to_string(),
+            title: format!("EAS: {}", eas_text::event_text(&on_air.event)),
+        };
+        self.queue
+            .submit(destination, &on_air, samples, sample_rate, info)
+            .await?;
 
-        #[derive(Clone)]
-        enum Segment {
-            File(PathBuf),
-            Silence,
-        }
+        Ok(RelayOutcome::Relayed)
+    }
+}
+
+/// One part of a relay bundle.
+enum BundleSegment {
+    File(PathBuf),
+    /// An alert recording whose SAME header is swapped for `forwarded`.
+    Forwarded {
+        recording: FinishedRecording,
+        original: Box<SameHeader>,
+        forwarded: Box<SameHeader>,
+    },
+}
 
-        let mut ordered_segments = Vec::new();
-        for (idx, segment) in audio_segments.into_iter().enumerate() {
-            if idx > 0 {
-                ordered_segments.push(Segment::Silence);
+impl BundleSegment {
+    fn decode(&self, sample_rate: u32) -> Result<Vec<f32>> {
+        match self {
+            BundleSegment::File(path) => media::decode_file_samples(path, sample_rate)
+                .with_context(|| format!("Failed to decode relay segment {}", path.display())),
+            BundleSegment::Forwarded {
+                recording,
+                original,
+                forwarded,
+            } => {
+                let (samples, recorded_rate) =
+                    recording::forwarded_recording(recording, original, forwarded)?;
+                let mut resampler = ChunkResampler::new(recorded_rate, sample_rate)?;
+                let mut out = Vec::with_capacity(samples.len());
+                resampler.process(&samples, &mut out)?;
+                resampler.flush(&mut out)?;
+                Ok(out)
             }
-            ordered_segments.push(Segment::File(segment));
         }
+    }
+}
 
-        if ordered_segments.is_empty() {
-            return Err(anyhow!("No segments available to relay"));
+/// Decodes each segment at the relay sample rate and joins them with a short
+/// gap of silence.
+fn build_bundle(segments: &[BundleSegment], sample_rate: u32) -> Result<Vec<f32>> {
+    let gap = vec![0.0; (sample_rate * SEGMENT_GAP_SECS) as usize];
+    let mut samples = Vec::new();
+    for (idx, segment) in segments.iter().enumerate() {
+        if idx > 0 {
+            samples.extend_from_slice(&gap);
         }
+        samples.extend(segment.decode(sample_rate)?);
+    }
+
+    if samples.is_empty() {
+        return Err(anyhow!("No audio available to relay"));
+    }
+    Ok(samples)
+}
 
-        let combined_temp = Builder::new()
-            .prefix("relay_combined_")
-            .suffix(".ogg")
-            .tempfile()
-            .context("Failed to allocate temporary relay file")?;
-        let combined_path = combined_temp.into_temp_path();
-        let combined_path_buf = combined_path.to_path_buf();
+/// The parts of a destination a continuous relay session depends on; any
+/// change to them reopens the mount.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct ContinuousSettings {
+    target: String,
+    format: StreamFormat,
+    program: ProgramSource,
+    station_name: String,
+}
 
-        let mut prepare = Command::new("ffmpeg");
-        prepare.arg("-nostdin");
-        prepare.arg("-hide_banner");
-        prepare.arg("-loglevel").arg("info");
-        prepare.arg("-y");
+impl ContinuousSettings {
+    fn from_config(config: &Config) -> HashMap<String, Self> {
+        if !config.should_relay {
+            return HashMap::new();
+        }
+        config
+            .relay_destinations
+            .iter()
+            .filter(|destination| destination.mode == RelayMode::Continuous)
+            .map(|destination| {
+                let settings = Self {
+                    target: destination.url.clone(),
+                    format: destination.format,
+                    program: destination.program.clone(),
+                    station_name: config.eas_relay_name.clone(),
+                };
+                (destination.name.clone(), settings)
+            })
+            .collect()
+    }
+}
+
+struct ContinuousTask {
+    settings: ContinuousSettings,
+    requests: mpsc::Sender<OverrideRequest>,
+    handle: JoinHandle<()>,
+}
+
+/// Keeps every destination in `continuous` mode fed with program audio,
+/// splicing in alerts as they arrive, and reopens mounts whose settings change.
+pub async fn run_continuous_relay(
+    config: ConfigHandle,
+    taps: StreamTaps,
+    mut requests: mpsc::Receiver<OverrideRequest>,
+) {
+    let mut updates = config.subscribe();
+    let mut tasks: HashMap<String, ContinuousTask> = HashMap::new();
+
+    loop {
+        let wanted = ContinuousSettings::from_config(&updates.borrow_and_update().config);
+        let stale: Vec<String> = tasks
+            .iter()
+            .filter(|(name, task)| wanted.get(*name) != Some(&task.settings))
+            .map(|(name, _)| name.clone())
+            .collect();
+        // Dropping a task's sender lets its session close the mount cleanly.
+        let mut previous: HashMap<String, JoinHandle<()>> = HashMap::new();
+        for name in stale {
+            if let Some(task) = tasks.remove(&name) {
+                info!(destination = %name, "Relay settings changed; closing continuous relay");
+                previous.insert(name, task.handle);
+            }
+        }
+        for (name, settings) in wanted {
+            if tasks.contains_key(&name) {
+                continue;
+            }
+            let (tx, rx) = mpsc::channel(8);
+            let handle = tokio::spawn(run_destination(
+                name.clone(),
+                settings.clone(),
+                taps.clone(),
+                rx,
+                previous.remove(&name),
+            ));
+            tasks.insert(
+                name,
+                ContinuousTask {
+                    settings,
+                    requests: tx,
+                    handle,
+                },
+            );
+        }
 
-        let mut input_count = 0u32;
-        for segment in &ordered_segments {
-            match segment {
-                Segment::File(path) => {
-                    prepare.arg("-i").arg(path);
+        loop {
+            tokio::select! {
+                changed = updates.changed() => {
+                    if changed.is_err() {
+                        return;
+                    }
+                    break;
                 }
-                Segment::Silence => {
-                    prepare
-                        .arg("-f")
-                        .arg("lavfi")
-                        .arg("-t")
-                        .arg("1")
-                        .arg("-i")
-                        .arg(format!(
-                            "anullsrc=channel_layout={}:sample_rate={}",
-                            TARGET_CHANNEL_LAYOUT, TARGET_SAMPLE_RATE
-                        ));
+                Some(request) = requests.recv() => {
+                    match tasks.get(&request.destination) {
+                        Some(task) => {
+                            if let Err(mpsc::error::SendError(request)) = task.requests.send(request).await {
+                                let _ = request
+                                    .done
+                                    .send(Err(anyhow!("Continuous relay task is not running")));
+                            }
+                        }
+                        None => {
+                            let _ = request.done.send(Err(anyhow!(
+                                "Relay destination '{}' is not in continuous mode",
+                                request.destination
+                            )));
+                        }
+                    }
                 }
             }
-            input_count += 1;
         }
+    }
+}
+
+/// Holds one continuous mount open, reconnecting after failures, until its
+/// request channel is closed.
+async fn run_destination(
+    name: String,
+    settings: ContinuousSettings,
+    taps: StreamTaps,
+    mut requests: mpsc::Receiver<OverrideRequest>,
+    previous: Option<JoinHandle<()>>,
+) {
+    // Let the session this one replaces release the mount first.
+    if let Some(previous) = previous {
+        let _ = previous.await;
+    }
 
-        if input_count == 0 {
-            return Err(anyhow!("Failed to prepare inputs for relay"));
+    loop {
+        match run_session(&settings, &taps, &mut requests).await {
+            Ok(()) => return,
+            Err(e) => warn!(
+                destination = %name,
+                "Continuous relay disconnected: {:#}. Reconnecting in {}s",
+                e,
+                RECONNECT_DELAY.as_secs()
+            ),
         }
 
-        let mut filter_parts = Vec::new();
-        let mut remapped_labels = Vec::new();
-        for idx in 0..input_count {
-            filter_parts.push(format!(
-                "[{}:a]aresample=sample_rate={},aformat=sample_rates={}:channel_layouts={},asetpts=N/SR/TB[s{}]",
-                idx,
-                TARGET_SAMPLE_RATE,
-                TARGET_SAMPLE_RATE,
-                TARGET_CHANNEL_LAYOUT,
-                idx
-            ));
-            remapped_labels.push(format!("[s{}]", idx));
+        // Alerts arriving while the mount is down fail rather than wait, so
+        // their history shows the relay did not go out.
+        let retry = sleep(RECONNECT_DELAY);
+        tokio::pin!(retry);
+        loop {
+            tokio::select! {
+                _ = &mut retry => break,
+                request = requests.recv() => match request {
+                    Some(request) => {
+                        let _ = request
+                            .done
+                            .send(Err(anyhow!("Continuous relay mount is disconnected")));
+                    }
+                    None => return,
+                },
+            }
         }
+    }
+}
 
-        let mut output_label = String::from("[s0]");
-        if input_count > 1 {
-            filter_parts.push(format!(
-                "{}concat=n={}:v=0:a=1[outa]",
-                remapped_labels.join(""),
-                remapped_labels.len()
-            ));
-            output_label = String::from("[outa]");
+/// Runs one connection to the relay mount. Returns `Ok` once the request
+/// channel closes and the mount has been shut down.
+async fn run_session(
+    settings: &ContinuousSettings,
+    taps: &StreamTaps,
+    requests: &mut mpsc::Receiver<OverrideRequest>,
+) -> Result<()> {
+    let target = IcecastTarget::parse(&settings.target)?;
+    let format = settings.format;
+    let mut program = ProgramFeed::open(&settings.program, taps, format.sample_rate).await?;
+    let info = StreamInfo {
+        name: settings.station_name.clone(),
+        description: RELAY_DESCRIPTION.to_string(),
+        title: settings.station_name.clone(),
+    };
+
+    let mut source = IcecastSource::connect(&target, format, &info).await?;
+    info!(
+        destination = %target,
+        format = %format,
+        program = %settings.program,
+        "Continuous relay connected"
+    );
+
+    let mut tick = interval(PROGRAM_TICK);
+    tick.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    let mut buffer = Vec::new();
+
+    loop {
+        tokio::select! {
+            request = requests.recv() => {
+                let Some(request) = request else {
+                    return source.finish().await;
+                };
+                let result = play_override(&mut source, &target, format, &info, &request).await;
+                program.skip_to_live();
+                match result {
+                    Ok(outcome) => {
+                        let _ = request.done.send(Ok(outcome));
+                    }
+                    Err(e) => {
+                        let _ = request.done.send(Err(anyhow!("{:#}", e)));
+                        return Err(e);
+                    }
+                }
+            }
+            _ = tick.tick() => {
+                let due = source.samples_due();
+                if due > 0 {
+                    buffer.clear();
+                    program.fill(due, &mut buffer)?;
+                    source.send_samples(&buffer).await?;
+                }
+            }
         }
+    }
+}
 
-        prepare.arg("-filter_complex").arg(filter_parts.join(";"));
-        prepare.arg("-map").arg(output_label);
-        prepare.arg("-ar").arg(TARGET_SAMPLE_RATE.to_string());
-        prepare.arg("-ac").arg("1");
-        prepare.arg("-c:a").arg("libvorbis");
-        prepare.arg("-b:a").arg("128k");
-        prepare.arg(&combined_path_buf);
+async fn play_override(
+    source: &mut IcecastSource,
+    target: &IcecastTarget,
+    format: StreamFormat,
+    info: &StreamInfo,
+    request: &OverrideRequest,
+) -> Result<PlayOutcome> {
+    let format_rate = format.sample_rate;
+    let mut resampled = Vec::new();
+    let samples = if request.sample_rate == format_rate {
+        &request.samples
+    } else {
+        let mut resampler = ChunkResampler::new(request.sample_rate, format_rate)?;
+        resampler.process(&request.samples, &mut resampled)?;
+        resampler.flush(&mut resampled)?;
+        &resampled
+    };
 
-        info!(path = %combined_path.display(), "Creating relay bundle with FFmpeg");
-        let prepare_status = prepare
-            .status()
-            .await
-            .context("Failed to execute ffmpeg bundle command")?;
+    info!(
+        destination = %target,
+        seconds = samples.len() as u64 / format_rate as u64,
+        "Alert override started on continuous relay"
+    );
+    if let Err(e) = icecast::update_metadata(target, format, &request.title).await {
+        warn!(destination = %target, "Could not update Icecast metadata: {:#}", e);
+    }
+    let outcome = play_preemptible(source, samples, &request.preempt).await?;
+    if let Err(e) = icecast::update_metadata(target, format, &info.title).await {
+        warn!(destination = %target, "Could not update Icecast metadata: {:#}", e);
+    }
+    if outcome == PlayOutcome::Completed {
+        info!(destination = %target, "Alert override finished; returning to program audio");
+    }
+    Ok(outcome)
+}
 
-        if !prepare_status.success() {
-            return Err(anyhow!(
-                "ffmpeg bundle process exited with status {:?}",
-                prepare_status.code()
-            ));
+/// Sends `samples` in short slices, stopping at a slice boundary once
+/// `preempt` is raised so the stream is never cut mid-sample.
+pub(crate) async fn play_preemptible(
+    source: &mut IcecastSource,
+    samples: &[f32],
+    preempt: &watch::Receiver<bool>,
+) -> Result<PlayOutcome> {
+    let slice = (source.sample_rate() as usize / 10).max(1);
+    for chunk in samples.chunks(slice) {
+        if *preempt.borrow() {
+            return Ok(PlayOutcome::Preempted);
         }
+        source.send_samples(chunk).await?;
+    }
+    Ok(PlayOutcome::Completed)
+}
 
-        let mut stream_cmd = Command::new("ffmpeg");
-        stream_cmd.arg("-nostdin");
-        stream_cmd.arg("-hide_banner");
-        stream_cmd.arg("-loglevel").arg("info");
-        stream_cmd.arg("-re");
-        stream_cmd.arg("-i").arg(&combined_path_buf);
-        stream_cmd.arg("-c:a").arg("copy");
-        stream_cmd.arg("-f").arg("wav");
-        stream_cmd
-            .arg("-metadata")
-            .arg(format!("title={}", "Emergency Alert"));
-        stream_cmd
-            .arg("-metadata")
-            .arg(format!("artist={}", "EAS Listener"));
-        stream_cmd.arg(&config.icecast_relay);
-
-        info!(destination = %config.icecast_relay, "Streaming relay audio to Icecast");
-        let stream_status = stream_cmd
-            .status()
-            .await
-            .context("Failed to execute ffmpeg relay stream command")?;
+/// Program audio for a continuous relay, produced on demand at the relay's
+/// sample rate.
+enum ProgramFeed {
+    Silence,
+    Stream {
+        rx: mpsc::Receiver<Vec<f32>>,
+        resampler: ChunkResampler,
+        buffer: VecDeque<f32>,
+        prebuffer: usize,
+        max_buffer: usize,
+        buffering: bool,
+    },
+    File {
+        samples: Vec<f32>,
+        position: usize,
+    },
+}
 
-        if !stream_status.success() {
-            return Err(anyhow!(
-                "ffmpeg relay stream process exited with status {:?}",
-                stream_status.code()
-            ));
+impl ProgramFeed {
+    async fn open(source: &ProgramSource, taps: &StreamTaps, sample_rate: u32) -> Result<Self> {
+        match source {
+            ProgramSource::Silence => Ok(ProgramFeed::Silence),
+            ProgramSource::Stream(url) => {
+                let samples_for =
+                    |duration: Duration| (duration.as_secs_f64() * sample_rate as f64) as usize;
+                Ok(ProgramFeed::Stream {
+                    rx: taps.subscribe(url),
+                    resampler: ChunkResampler::new(TARGET_SAMPLE_RATE, sample_rate)?,
+                    buffer: VecDeque::new(),
+                    prebuffer: samples_for(PROGRAM_PREBUFFER),
+                    max_buffer: samples_for(PROGRAM_MAX_BUFFER),
+                    buffering: true,
+                })
+            }
+            ProgramSource::File(path) => {
+                let path = path.clone();
+                let decoded = tokio::task::spawn_blocking(move || {
+                    media::decode_file_samples(&path, sample_rate)
+                })
+                .await
+                .context("Filler decode task failed")?;
+                match decoded {
+                    Ok(samples) if !samples.is_empty() => Ok(ProgramFeed::File {
+                        samples,
+                        position: 0,
+                    }),
+                    Ok(_) => {
+                        warn!("Relay filler {} has no audio; using silence", source);
+                        Ok(ProgramFeed::Silence)
+                    }
+                    Err(e) => {
+                        warn!("Could not decode relay filler: {:#}; using silence", e);
+                        Ok(ProgramFeed::Silence)
+                    }
+                }
+            }
         }
+    }
 
-        combined_path
-            .close()
-            .context("Failed to clean up temporary relay bundle")?;
+    /// Appends exactly `count` samples, padding with silence when a monitored
+    /// stream has not delivered enough audio.
+    fn fill(&mut self, count: usize, out: &mut Vec<f32>) -> Result<()> {
+        let start = out.len();
+        match self {
+            ProgramFeed::Silence => {}
+            ProgramFeed::Stream {
+                rx,
+                resampler,
+                buffer,
+                prebuffer,
+                max_buffer,
+                buffering,
+            } => {
+                let mut incoming = Vec::new();
+                while let Ok(chunk) = rx.try_recv() {
+                    resampler.process(&chunk, &mut incoming)?;
+                }
+                buffer.extend(incoming);
+                if buffer.len() > *max_buffer {
+                    let excess = buffer.len() - *max_buffer;
+                    buffer.drain(..excess);
+                }
 
+                if *buffering && buffer.len() >= *prebuffer {
+                    *buffering = false;
+                }
+                if !*buffering {
+                    let take = count.min(buffer.len());
+                    out.extend(buffer.drain(..take));
+                    if take < count {
+                        *buffering = true;
+                    }
+                }
+            }
+            ProgramFeed::File { samples, position } => {
+                while out.len() - start < count {
+                    let take = (count - (out.len() - start)).min(samples.len() - *position);
+                    out.extend_from_slice(&samples[*position..*position + take]);
+                    *position = (*position + take) % samples.len();
+                }
+            }
+        }
+        out.resize(start + count, 0.0);
         Ok(())
     }
+
+    /// Drops program audio that queued up while an alert was on the air.
+    fn skip_to_live(&mut self) {
+        if let ProgramFeed::Stream {
+            rx,
+            buffer,
+            buffering,
+            ..
+        } = self
+        {
+            while rx.try_recv().is_ok() {}
+            buffer.clear();
+            *buffering = true;
+        }
+    }
 }
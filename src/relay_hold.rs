@@ -0,0 +1,124 @@
+use crate::filter::{HoldDefault, HoldPolicy};
+use crate::monitoring::{MonitoringHub, PendingRelayPayload};
+use crate::state::ActiveAlert;
+use chrono::Utc;
+use parking_lot::Mutex;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::oneshot;
+use tracing::info;
+
+static NEXT_HOLD_ID: AtomicU64 = AtomicU64::new(1);
+
+/// How a held relay was released.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HoldVerdict {
+    Approved,
+    Cancelled,
+    Expired(HoldDefault),
+}
+
+impl HoldVerdict {
+    pub fn should_relay(&self) -> bool {
+        matches!(
+            self,
+            HoldVerdict::Approved | HoldVerdict::Expired(HoldDefault::Relay)
+        )
+    }
+}
+
+struct PendingRelay {
+    payload: PendingRelayPayload,
+    decide: oneshot::Sender<bool>,
+}
+
+/// Relays held for operator review, keyed by hold id. Each entry waits for
+/// an approve or cancel from the API, or for its timer to run out.
+#[derive(Clone)]
+pub struct RelayHolds {
+    pending: Arc<Mutex<BTreeMap<u64, PendingRelay>>>,
+    monitoring: MonitoringHub,
+}
+
+impl RelayHolds {
+    pub fn new(monitoring: MonitoringHub) -> Self {
+        Self {
+            pending: Arc::new(Mutex::new(BTreeMap::new())),
+            monitoring,
+        }
+    }
+
+    /// Holds `alert`'s relay to `destination` until an operator decides or
+    /// `policy` expires.
+    pub async fn hold(
+        &self,
+        destination: &str,
+        alert: &ActiveAlert,
+        rule: Option<&str>,
+        policy: HoldPolicy,
+    ) -> HoldVerdict {
+        let id = NEXT_HOLD_ID.fetch_add(1, Ordering::Relaxed);
+        let held_at = Utc::now();
+        let (decide, decision) = oneshot::channel();
+        let payload = PendingRelayPayload {
+            id,
+            destination: destination.to_string(),
+            header: alert.raw_header.clone(),
+            event_code: alert.data.event_code.clone(),
+            event_text: alert.data.event_text.clone(),
+            rule: rule.map(str::to_string),
+            hold_secs: policy.secs,
+            default_action: policy.default.as_str().to_string(),
+            held_at,
+            expires_at: held_at + chrono::Duration::seconds(policy.secs as i64),
+        };
+
+        info!(
+            id,
+            destination,
+            event_code = %payload.event_code,
+            hold_secs = policy.secs,
+            default = policy.default.as_str(),
+            "Relay held for operator approval"
+        );
+        self.pending
+            .lock()
+            .insert(id, PendingRelay { payload, decide });
+        self.publish();
+
+        let verdict = match tokio::time::timeout(Duration::from_secs(policy.secs), decision).await {
+            Ok(Ok(true)) => HoldVerdict::Approved,
+            Ok(Ok(false)) => HoldVerdict::Cancelled,
+            Ok(Err(_)) | Err(_) => HoldVerdict::Expired(policy.default),
+        };
+
+        if self.pending.lock().remove(&id).is_some() {
+            self.publish();
+        }
+        info!(id, destination, verdict = ?verdict, "Relay hold released");
+        verdict
+    }
+
+    /// Approves or cancels a held relay. Returns `false` if no relay with
+    /// that id is waiting.
+    pub fn decide(&self, id: u64, approve: bool) -> bool {
+        let Some(pending) = self.pending.lock().remove(&id) else {
+            return false;
+        };
+        self.publish();
+        let _ = pending.decide.send(approve);
+        true
+    }
+
+    fn publish(&self) {
+        let pending = self
+            .pending
+            .lock()
+            .values()
+            .map(|pending| pending.payload.clone())
+            .collect();
+        self.monitoring.update_pending_relays(pending);
+    }
+}
@@ -0,0 +1,351 @@
+use crate::eas_text;
+use crate::header::SameHeader;
+use crate::icecast::{self, IcecastSource, IcecastTarget, StreamInfo};
+use crate::monitoring::{MonitoringHub, QueuedRelayPayload, RelayQueuePayload};
+use crate::relay::{self, ContinuousRelay, RelayDestination, RelayMode};
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use parking_lot::Mutex;
+use sameold::{EventCode, SignificanceLevel};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use tokio::sync::{oneshot, watch, Notify};
+use tracing::{info, warn};
+
+static NEXT_JOB_ID: AtomicU64 = AtomicU64::new(1);
+
+/// Relay order, lowest first. National activations (EAN, NPT, ...) outrank
+/// everything, and tests go last.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum RelayPriority {
+    Test,
+    Statement,
+    Advisory,
+    Watch,
+    Warning,
+    National,
+}
+
+impl RelayPriority {
+    pub fn for_event(code: &str) -> Self {
+        let event = EventCode::from(code);
+        if event.phenomenon().is_national() {
+            return RelayPriority::National;
+        }
+        if event.is_test() {
+            return RelayPriority::Test;
+        }
+        match event.significance() {
+            SignificanceLevel::Test => RelayPriority::Test,
+            SignificanceLevel::Statement => RelayPriority::Statement,
+            SignificanceLevel::Emergency => RelayPriority::Advisory,
+            SignificanceLevel::Watch => RelayPriority::Watch,
+            // Unknown significance is treated as a warning, as sameold advises.
+            SignificanceLevel::Warning | SignificanceLevel::Unknown => RelayPriority::Warning,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RelayPriority::Test => "test",
+            RelayPriority::Statement => "statement",
+            RelayPriority::Advisory => "advisory",
+            RelayPriority::Watch => "watch",
+            RelayPriority::Warning => "warning",
+            RelayPriority::National => "national",
+        }
+    }
+}
+
+/// How a relay job left the air.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlayOutcome {
+    Completed,
+    Preempted,
+}
+
+struct RelayJob {
+    id: u64,
+    priority: RelayPriority,
+    header: String,
+    event_code: String,
+    event_text: String,
+    info: StreamInfo,
+    destination: RelayDestination,
+    samples: Arc<Vec<f32>>,
+    sample_rate: u32,
+    queued_at: DateTime<Utc>,
+    started_at: Option<DateTime<Utc>>,
+    preemptions: u32,
+    done: oneshot::Sender<Result<()>>,
+}
+
+impl RelayJob {
+    fn payload(&self) -> QueuedRelayPayload {
+        QueuedRelayPayload {
+            id: self.id,
+            header: self.header.clone(),
+            event_code: self.event_code.clone(),
+            event_text: self.event_text.clone(),
+            priority: self.priority.as_str().to_string(),
+            queued_at: self.queued_at,
+            started_at: self.started_at,
+            preemptions: self.preemptions,
+        }
+    }
+}
+
+struct PlayingJob {
+    payload: QueuedRelayPayload,
+    priority: RelayPriority,
+    preempt: watch::Sender<bool>,
+}
+
+#[derive(Default)]
+struct DestinationQueue {
+    pending: Vec<RelayJob>,
+    playing: Option<PlayingJob>,
+    wake: Arc<Notify>,
+}
+
+impl DestinationQueue {
+    /// Index of the job that should play next: highest priority, then the
+    /// one that has waited longest.
+    fn next_index(&self) -> Option<usize> {
+        self.pending
+            .iter()
+            .enumerate()
+            .max_by(|(_, a), (_, b)| a.priority.cmp(&b.priority).then(b.id.cmp(&a.id)))
+            .map(|(idx, _)| idx)
+    }
+
+    fn payload(&self, destination: &str) -> RelayQueuePayload {
+        let mut pending: Vec<&RelayJob> = self.pending.iter().collect();
+        pending.sort_by(|a, b| b.priority.cmp(&a.priority).then(a.id.cmp(&b.id)));
+        RelayQueuePayload {
+            destination: destination.to_string(),
+            playing: self.playing.as_ref().map(|job| job.payload.clone()),
+            pending: pending.into_iter().map(RelayJob::payload).collect(),
+        }
+    }
+}
+
+/// Serializes relay output per destination. Each destination gets one worker
+/// that plays queued alerts in priority order; a new alert that outranks the
+/// one on the air cuts it off, and the interrupted alert is replayed later.
+#[derive(Clone)]
+pub struct RelayQueue {
+    queues: Arc<Mutex<HashMap<String, DestinationQueue>>>,
+    monitoring: MonitoringHub,
+    continuous: ContinuousRelay,
+}
+
+impl RelayQueue {
+    pub fn new(monitoring: MonitoringHub, continuous: ContinuousRelay) -> Self {
+        Self {
+            queues: Arc::new(Mutex::new(HashMap::new())),
+            monitoring,
+            continuous,
+        }
+    }
+
+    /// Queues `samples` for `destination` and waits until they have been
+    /// played in full. `header` is the header that goes out on the air, which
+    /// may carry a different event code than the alert as received.
+    pub async fn submit(
+        &self,
+        destination: &RelayDestination,
+        header: &SameHeader,
+        samples: Vec<f32>,
+        sample_rate: u32,
+        info: StreamInfo,
+    ) -> Result<()> {
+        let (done, result) = oneshot::channel();
+        let job = RelayJob {
+            id: NEXT_JOB_ID.fetch_add(1, Ordering::Relaxed),
+            priority: RelayPriority::for_event(&header.event),
+            header: header.to_string(),
+            event_code: header.event.clone(),
+            event_text: eas_text::event_text(&header.event),
+            info,
+            destination: destination.clone(),
+            samples: Arc::new(samples),
+            sample_rate,
+            queued_at: Utc::now(),
+            started_at: None,
+            preemptions: 0,
+            done,
+        };
+
+        let payload = {
+            let mut queues = self.queues.lock();
+            let queue = queues.entry(destination.name.clone()).or_insert_with(|| {
+                let queue = DestinationQueue::default();
+                tokio::spawn(run_worker(
+                    self.clone(),
+                    destination.name.clone(),
+                    queue.wake.clone(),
+                ));
+                queue
+            });
+
+            if let Some(playing) = &queue.playing {
+                if job.priority > playing.priority {
+                    info!(
+                        destination = %destination.name,
+                        event_code = %job.event_code,
+                        preempted = %playing.payload.event_code,
+                        "Higher-priority alert preempts the relay on the air"
+                    );
+                    let _ = playing.preempt.send(true);
+                }
+            }
+            info!(
+                destination = %destination.name,
+                event_code = %job.event_code,
+                priority = job.priority.as_str(),
+                waiting = queue.pending.len() + usize::from(queue.playing.is_some()),
+                "Relay queued"
+            );
+            queue.pending.push(job);
+            queue.wake.notify_one();
+            queue.payload(&destination.name)
+        };
+        self.monitoring.update_relay_queue(payload);
+
+        result
+            .await
+            .map_err(|_| anyhow!("Relay queue dropped the alert"))?
+    }
+
+    /// Moves the next job for `destination` on the air.
+    fn start_next(&self, destination: &str) -> Option<(RelayJob, watch::Receiver<bool>)> {
+        let (job, preempt_rx, payload) = {
+            let mut queues = self.queues.lock();
+            let queue = queues.get_mut(destination)?;
+            let idx = queue.next_index()?;
+            let mut job = queue.pending.swap_remove(idx);
+            job.started_at = Some(Utc::now());
+            let (preempt, preempt_rx) = watch::channel(false);
+            queue.playing = Some(PlayingJob {
+                payload: job.payload(),
+                priority: job.priority,
+                preempt,
+            });
+            (job, preempt_rx, queue.payload(destination))
+        };
+        self.monitoring.update_relay_queue(payload);
+        Some((job, preempt_rx))
+    }
+
+    /// Takes `job` off the air, either finishing it or putting it back in
+    /// line after a preemption.
+    fn finish(&self, destination: &str, mut job: RelayJob, result: Result<PlayOutcome>) {
+        let payload = {
+            let mut queues = self.queues.lock();
+            let Some(queue) = queues.get_mut(destination) else {
+                return;
+            };
+            queue.playing = None;
+            match result {
+                Ok(PlayOutcome::Preempted) => {
+                    info!(
+                        destination,
+                        event_code = %job.event_code,
+                        "Relay preempted; it will be replayed after higher-priority alerts"
+                    );
+                    job.started_at = None;
+                    job.preemptions += 1;
+                    queue.pending.push(job);
+                }
+                Ok(PlayOutcome::Completed) => {
+                    let _ = job.done.send(Ok(()));
+                }
+                Err(e) => {
+                    let _ = job.done.send(Err(e));
+                }
+            }
+            queue.payload(destination)
+        };
+        self.monitoring.update_relay_queue(payload);
+    }
+}
+
+async fn run_worker(queue: RelayQueue, destination: String, wake: Arc<Notify>) {
+    // An on-alert mount stays connected while alerts are waiting, so a queue
+    // of alerts goes out back to back on one connection.
+    let mut connection: Option<(IcecastSource, RelayDestination)> = None;
+
+    loop {
+        let Some((job, preempt)) = queue.start_next(&destination) else {
+            if let Some((source, _)) = connection.take() {
+                if let Err(e) = source.finish().await {
+                    warn!(destination = %destination, "Failed to close relay connection: {:#}", e);
+                }
+                continue;
+            }
+            wake.notified().await;
+            continue;
+        };
+
+        let result = match job.destination.mode {
+            RelayMode::Continuous => {
+                if let Some((source, _)) = connection.take() {
+                    let _ = source.finish().await;
+                }
+                queue
+                    .continuous
+                    .play(
+                        &job.destination.name,
+                        job.samples.clone(),
+                        job.sample_rate,
+                        job.info.title.clone(),
+                        preempt,
+                    )
+                    .await
+            }
+            RelayMode::OnAlert => play_on_alert(&mut connection, &job, preempt).await,
+        };
+        if result.is_err() {
+            connection = None;
+        }
+        queue.finish(&destination, job, result);
+    }
+}
+
+async fn play_on_alert(
+    connection: &mut Option<(IcecastSource, RelayDestination)>,
+    job: &RelayJob,
+    preempt: watch::Receiver<bool>,
+) -> Result<PlayOutcome> {
+    let destination = &job.destination;
+    let target = IcecastTarget::parse(&destination.url)?;
+    let reusable = connection.as_ref().is_some_and(|(_, current)| {
+        current.url == destination.url && current.format == destination.format
+    });
+
+    if reusable {
+        if let Err(e) = icecast::update_metadata(&target, destination.format, &job.info.title).await
+        {
+            warn!(destination = %target, "Could not update Icecast metadata: {:#}", e);
+        }
+    } else {
+        if let Some((source, _)) = connection.take() {
+            let _ = source.finish().await;
+        }
+        let source = IcecastSource::connect(&target, destination.format, &job.info).await?;
+        *connection = Some((source, destination.clone()));
+    }
+
+    let (source, _) = connection
+        .as_mut()
+        .expect("relay connection was just established");
+    info!(
+        destination = %target,
+        format = %destination.format,
+        seconds = job.samples.len() as u64 / job.sample_rate as u64,
+        "Streaming relay audio to Icecast"
+    );
+    relay::play_preemptible(source, &job.samples, &preempt).await
+}
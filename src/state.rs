@@ -1,4 +1,6 @@
-use crate::filter::{self, FilterRule};
+use crate::attention::AttentionDetection;
+use crate::filter::AlertDecision;
+use crate::header::SameHeader;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use std::time::Duration;
@@ -13,47 +15,64 @@ pub struct EasAlertData {
     pub originator: String,
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 #[allow(dead_code)]
 pub struct ActiveAlert {
     pub data: EasAlertData,
+    pub header: SameHeader,
     pub raw_header: String,
     #[serde(with = "chrono::serde::ts_seconds")]
     pub received_at: DateTime<Utc>,
     #[serde(with = "chrono::serde::ts_seconds")]
     pub expires_at: DateTime<Utc>,
     pub purge_time: Duration,
+    pub sources: Vec<String>,
+    #[serde(default)]
+    pub decision: AlertDecision,
+    /// Attention signals heard while this alert's header was open.
+    #[serde(default)]
+    pub attention: Vec<AttentionDetection>,
 }
 
 impl ActiveAlert {
-    pub fn new(data: EasAlertData, raw_header: String, purge_time: Duration) -> Self {
+    pub fn new(
+        data: EasAlertData,
+        header: SameHeader,
+        purge_time: Duration,
+        source_stream: String,
+    ) -> Self {
         let received_at = Utc::now();
         let expires_at = received_at + purge_time;
         Self {
             data,
-            raw_header,
+            raw_header: header.to_string(),
+            header,
             received_at,
             expires_at,
             purge_time,
+            sources: vec![source_stream],
+            decision: AlertDecision::default(),
+            attention: Vec::new(),
+        }
+    }
+
+    pub fn add_source(&mut self, source_stream: &str) -> bool {
+        if self.sources.iter().any(|source| source == source_stream) {
+            return false;
         }
+        self.sources.push(source_stream.to_string());
+        true
     }
 }
 
 pub struct AppState {
     pub active_alerts: Vec<ActiveAlert>,
-    filters: Vec<FilterRule>,
 }
 
 impl AppState {
-    pub fn new(filters: Vec<FilterRule>) -> Self {
-        filter::install_filters(filters.clone());
+    pub fn new() -> Self {
         Self {
             active_alerts: Vec::new(),
-            filters,
         }
     }
-
-    pub fn cloned_filters(&self) -> Vec<FilterRule> {
-        self.filters.clone()
-    }
 }
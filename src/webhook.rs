@@ -1,71 +1,60 @@
-use crate::filter;
+use crate::relay::RelayResult;
 use crate::state::ActiveAlert;
 use crate::Config;
 use base64::Engine;
 use chrono::Local;
 use inflector::Inflector;
-use lazy_static::lazy_static;
 use reqwest::header::AUTHORIZATION;
 use reqwest::{multipart, Client};
+use serde::{Deserialize, Serialize};
 use serde_json::json;
-use std::collections::HashMap;
 use std::fs;
 use std::io::Read;
 use std::path::PathBuf;
 use tokio::process::Command;
 use tracing::{info, warn};
 
-lazy_static! {
-    static ref json_config: Config =
-        Config::from_config_json("/app/config.json").expect("Failed to load config");
-    static ref station_name: String = json_config.eas_relay_name.clone();
-    static ref STREAM_INDEX_MAP: HashMap<String, usize> = json_config
-        .icecast_stream_urls
-        .iter()
-        .enumerate()
-        .map(|(idx, url)| (url.clone(), idx + 1))
-        .collect();
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NotificationResult {
+    pub channel: String,
+    pub success: bool,
+    pub detail: Option<String>,
+}
+
+impl NotificationResult {
+    fn ok(channel: &str) -> Self {
+        Self {
+            channel: channel.to_string(),
+            success: true,
+            detail: None,
+        }
+    }
+
+    fn failed(channel: &str, detail: impl Into<String>) -> Self {
+        Self {
+            channel: channel.to_string(),
+            success: false,
+            detail: Some(detail.into()),
+        }
+    }
 }
 
 pub async fn send_alert_webhook(
+    json_config: &Config,
     url: &str,
     alert: &ActiveAlert,
     _dsame_text: &str,
-    _raw_header: &str,
     recording_path: Option<PathBuf>,
-) {
+) -> Vec<NotificationResult> {
+    let mut results = Vec::new();
     let config_path = json_config.apprise_config_path.to_string();
-    let apprise_urls_from_config_array: Vec<String> = match fs::File::open(&config_path) {
-        Ok(mut file) => {
-            let mut contents = String::new();
-            if let Err(err) = file.read_to_string(&mut contents) {
-                warn!(
-                    "Failed to read AppRise config file at '{}': {}",
-                    config_path, err
-                );
-                return;
-            }
-            contents
-                .lines()
-                .map(str::trim)
-                .filter(|line| !line.is_empty() && !line.starts_with('#'))
-                .map(|line| {
-                    line.strip_prefix('-')
-                        .map(str::trim_start)
-                        .unwrap_or(line)
-                        .to_owned()
-                })
-                .collect()
-        }
-        Err(err) => {
-            warn!(
-                "Failed to open AppRise config file at '{}': {}",
-                config_path, err
-            );
-            return;
+    let apprise_urls_from_config_array = match load_apprise_urls(&config_path) {
+        Ok(urls) => urls,
+        Err(failure) => {
+            results.push(failure);
+            return results;
         }
     };
-    let should_relay_dasdec = json_config.should_relay_dasdec;
     let dasdec_url = json_config.dasdec_url.clone();
     let data = &alert.data;
     let event_title = data.event_text.to_title_case();
@@ -87,14 +76,16 @@ pub async fn send_alert_webhook(
         None
     };
     let discord_embed_body = build_discord_embed_body(
-        &url,
+        json_config,
+        url,
         &event_title,
         &data.originator,
         &received_timestamp,
         &data.eas_text,
-        &alert.raw_header,
+        alert,
     );
     let markdown_body = build_markdown_body(
+        &json_config.eas_relay_name,
         &event_title,
         &data.originator,
         &received_timestamp,
@@ -102,6 +93,7 @@ pub async fn send_alert_webhook(
         &alert.raw_header,
     );
     let html_body = build_html_body(
+        &json_config.eas_relay_name,
         &event_title,
         &data.originator,
         &received_timestamp,
@@ -109,6 +101,7 @@ pub async fn send_alert_webhook(
         &alert.raw_header,
     );
     let text_body = build_plain_body(
+        &json_config.eas_relay_name,
         &event_title,
         &data.originator,
         &received_timestamp,
@@ -116,9 +109,7 @@ pub async fn send_alert_webhook(
         &alert.raw_header,
     );
 
-    let filter_relay = filter::should_relay_alert(&alert.raw_header[9..12]);
-
-    if should_relay_dasdec && !dasdec_url.trim().is_empty() && filter_relay {
+    if alert.decision.dasdec {
         let client = Client::new();
 
         let use_reverse_proxy = json_config.use_reverse_proxy;
@@ -199,6 +190,7 @@ pub async fn send_alert_webhook(
         match client.post(&dasdec_url).form(&dasdec_payload).send().await {
             Ok(response) if response.status().is_success() => {
                 info!("Successfully relayed alert to DASDEC");
+                results.push(NotificationResult::ok("dasdec"));
             }
             Ok(response) => {
                 let status = response.status();
@@ -207,9 +199,14 @@ pub async fn send_alert_webhook(
                     "DASDEC relay failed with status {}: body='{}'",
                     status, body
                 );
+                results.push(NotificationResult::failed(
+                    "dasdec",
+                    format!("status {}", status),
+                ));
             }
             Err(err) => {
                 warn!("Failed to send DASDEC relay request: {}", err);
+                results.push(NotificationResult::failed("dasdec", err.to_string()));
             }
         }
     }
@@ -273,21 +270,28 @@ pub async fn send_alert_webhook(
             );
 
             match client.post(&url).multipart(form).send().await {
-                Ok(response) if response.status().is_success() => {}
+                Ok(response) if response.status().is_success() => {
+                    results.push(NotificationResult::ok("discord"));
+                }
                 Ok(response) => {
                     warn!(
                         "Discord webhook responded with status {} for '{}'",
                         response.status(),
                         discord_url
                     );
+                    results.push(NotificationResult::failed(
+                        "discord",
+                        format!("status {}", response.status()),
+                    ));
                 }
                 Err(e) => {
                     warn!("Failed to send Discord webhook '{}': {}", discord_url, e);
+                    results.push(NotificationResult::failed("discord", e.to_string()));
                 }
             }
         }
 
-        return;
+        return results;
     }
 
     let attempts = [
@@ -309,7 +313,8 @@ pub async fn send_alert_webhook(
 
         match command.output().await {
             Ok(output) if output.status.success() => {
-                return;
+                results.push(NotificationResult::ok("apprise"));
+                return results;
             }
             Ok(output) => {
                 let stdout = String::from_utf8_lossy(&output.stdout);
@@ -332,28 +337,194 @@ pub async fn send_alert_webhook(
     }
 
     warn!("Unable to deliver notification via AppRise after trying all formats");
+    results.push(NotificationResult::failed(
+        "apprise",
+        "delivery failed in every input format",
+    ));
+    results
+}
+
+/// Sends a plain-text station notice (not an alert) to the configured
+/// Discord webhooks, or through the AppRise CLI when there are none.
+pub async fn send_status_notification(
+    json_config: &Config,
+    title: &str,
+    body: &str,
+) -> Vec<NotificationResult> {
+    send_notice(json_config, title, body, &[]).await
+}
+
+/// Reports how an alert's relays went, with one field per destination.
+pub async fn send_relay_notification(
+    json_config: &Config,
+    alert: &ActiveAlert,
+    relays: &[RelayResult],
+) -> Vec<NotificationResult> {
+    let title = format!("{} relay results", alert.data.event_text.to_title_case());
+    let body = format!("EAS Protocol Data: {}", alert.raw_header.trim_end());
+    let fields: Vec<(String, String)> = relays
+        .iter()
+        .map(|relay| (relay.destination.clone(), relay.to_string()))
+        .collect();
+    send_notice(json_config, &title, &body, &fields).await
+}
+
+/// Sends a notice with optional name/value fields: embed fields on Discord,
+/// one line each in the AppRise body.
+async fn send_notice(
+    json_config: &Config,
+    title: &str,
+    body: &str,
+    fields: &[(String, String)],
+) -> Vec<NotificationResult> {
+    let config_path = json_config.apprise_config_path.as_str();
+    let apprise_urls = match load_apprise_urls(config_path) {
+        Ok(urls) => urls,
+        Err(failure) => return vec![failure],
+    };
+
+    let discord_urls: Vec<&str> = apprise_urls
+        .iter()
+        .map(|url| url.trim())
+        .filter(|url| url.starts_with("discord://"))
+        .collect();
+
+    if !discord_urls.is_empty() {
+        let client = Client::new();
+        let embed_fields: Vec<serde_json::Value> = fields
+            .iter()
+            .map(|(name, value)| json!({ "name": name, "value": value, "inline": true }))
+            .collect();
+        let payload = json!({
+            "embeds": [{
+                "title": title,
+                "description": body,
+                "fields": embed_fields,
+                "timestamp": Local::now().to_rfc3339(),
+            }]
+        });
+        let mut results = Vec::new();
+        for discord_url in discord_urls {
+            let url = format!(
+                "https://discord.com/api/webhooks/{}",
+                discord_url.trim_start_matches("discord://")
+            );
+            match client.post(&url).json(&payload).send().await {
+                Ok(response) if response.status().is_success() => {
+                    results.push(NotificationResult::ok("discord"));
+                }
+                Ok(response) => {
+                    warn!(
+                        "Discord webhook responded with status {} for '{}'",
+                        response.status(),
+                        discord_url
+                    );
+                    results.push(NotificationResult::failed(
+                        "discord",
+                        format!("status {}", response.status()),
+                    ));
+                }
+                Err(e) => {
+                    warn!("Failed to send Discord webhook '{}': {}", discord_url, e);
+                    results.push(NotificationResult::failed("discord", e.to_string()));
+                }
+            }
+        }
+        return results;
+    }
+
+    let mut command = Command::new("apprise");
+    command.arg("--config").arg(config_path);
+    command.arg("--title").arg(title);
+    let mut text_body = body.to_string();
+    for (name, value) in fields {
+        text_body.push_str(&format!("\n{}: {}", name, value));
+    }
+    command.arg("--body").arg(text_body);
+    command.arg("--input-format").arg("text");
+
+    match command.output().await {
+        Ok(output) if output.status.success() => vec![NotificationResult::ok("apprise")],
+        Ok(output) => {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            warn!(
+                "AppRise CLI failed (exit code {:?}): stderr='{}'",
+                output.status.code(),
+                stderr.trim()
+            );
+            vec![NotificationResult::failed(
+                "apprise",
+                format!("exit code {:?}", output.status.code()),
+            )]
+        }
+        Err(err) => {
+            warn!("Failed to invoke AppRise CLI: {}", err);
+            vec![NotificationResult::failed("apprise", err.to_string())]
+        }
+    }
+}
+
+/// Reads the notification URLs from the AppRise config, skipping blank lines
+/// and comments.
+fn load_apprise_urls(config_path: &str) -> Result<Vec<String>, NotificationResult> {
+    match fs::File::open(config_path) {
+        Ok(mut file) => {
+            let mut contents = String::new();
+            if let Err(err) = file.read_to_string(&mut contents) {
+                warn!(
+                    "Failed to read AppRise config file at '{}': {}",
+                    config_path, err
+                );
+                return Err(NotificationResult::failed(
+                    "apprise",
+                    format!("failed to read config: {}", err),
+                ));
+            }
+            Ok(contents
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                .map(|line| {
+                    line.strip_prefix('-')
+                        .map(str::trim_start)
+                        .unwrap_or(line)
+                        .to_owned()
+                })
+                .collect())
+        }
+        Err(err) => {
+            warn!(
+                "Failed to open AppRise config file at '{}': {}",
+                config_path, err
+            );
+            Err(NotificationResult::failed(
+                "apprise",
+                format!("failed to open config: {}", err),
+            ))
+        }
+    }
 }
 
 fn build_discord_embed_body(
+    json_config: &Config,
     stream_id: &str,
     title: &str,
     originator: &str,
     received_timestamp: &str,
     eas_text: &str,
-    raw_header: &str,
+    alert: &ActiveAlert,
 ) -> serde_json::Value {
-    let monitor_number = STREAM_INDEX_MAP.get(stream_id).copied().unwrap_or(999);
-    let event_code = raw_header[9..12]
-        .chars()
-        .filter(|c| c.is_ascii_alphabetic())
-        .collect::<String>();
-    let filter_name = filter::determine_filter_name(&event_code);
-
-    let img_name = if !raw_header.is_empty() && raw_header.len() >= 12 {
-        &event_code
-    } else {
-        "ZZZ"
-    };
+    let header = &alert.header;
+    let monitor_number = json_config
+        .icecast_stream_urls
+        .iter()
+        .position(|url| url == stream_id)
+        .map(|idx| idx + 1)
+        .unwrap_or(999);
+    let filter_name = alert.decision.rule_name();
+    let schedule = alert.decision.schedule.as_deref().unwrap_or("Always");
+    let img_name = header.event.as_str();
+    let raw_header = header.to_string();
 
     let img_color = if title.to_lowercase().contains("test") {
         "105733"
@@ -375,7 +546,7 @@ fn build_discord_embed_body(
             Err(error) => format!("Error during parsing: {}", error),
         },
         "author": {
-            "name": format!("{} - Software ENDEC Logs", station_name.as_str()),
+            "name": format!("{} - Software ENDEC Logs", json_config.eas_relay_name.as_str()),
             "icon_url": format!("https://wagspuzzle.space/assets/eas-icons/index.php?code={}&hex=0x{}", img_name, img_color),
             "url": "https://github.com/wagwan-piffting-blud/ASMARA_Rust"
         },
@@ -400,6 +571,11 @@ fn build_discord_embed_body(
                 "value": filter_name,
                 "inline": true
             },
+            {
+                "name": "Schedule",
+                "value": schedule,
+                "inline": true
+            },
             {
                 "name": "EAS Text Data:",
                 "value": format!("```\n{}\n```", eas_text.trim_end()),
@@ -413,10 +589,11 @@ fn build_discord_embed_body(
         ]
     });
 
-    return embed;
+    embed
 }
 
 fn build_markdown_body(
+    station_name: &str,
     title: &str,
     originator: &str,
     received_timestamp: &str,
@@ -425,7 +602,7 @@ fn build_markdown_body(
 ) -> String {
     format!(
         "**{} - Software ENDEC Logs**\n\n**{}** has just been received from: {}\n\n**Received:** {}\n\n**EAS Text Data:**\n```\n{}\n```\n\n**EAS Protocol Data:**\n```\n{}\n```\n\nPowered by [Wags' Software ENDEC](https://github.com/wagwan-piffting-blud/ASMARA_Rust)",
-        station_name.as_str(),
+        station_name,
         title,
         originator,
         received_timestamp,
@@ -435,6 +612,7 @@ fn build_markdown_body(
 }
 
 fn build_html_body(
+    station_name: &str,
     title: &str,
     originator: &str,
     received_timestamp: &str,
@@ -450,7 +628,7 @@ fn build_html_body(
          <p><strong>EAS Protocol Data:</strong></p>\
          <pre>{}</pre>\
          <p>Powered by <a href=\"https://github.com/wagwan-piffting-blud/ASMARA_Rust\">Wags' Software ENDEC</a></p>",
-        html_escape(&station_name.as_str()),
+        html_escape(station_name),
         html_escape(title),
         html_escape(originator),
         html_escape(received_timestamp),
@@ -460,6 +638,7 @@ fn build_html_body(
 }
 
 fn build_plain_body(
+    station_name: &str,
     title: &str,
     originator: &str,
     received_timestamp: &str,
@@ -468,7 +647,7 @@ fn build_plain_body(
 ) -> String {
     format!(
         "{} - Software ENDEC Logs\n\n{} has just been received from: {}\nReceived: {}\n\nEAS Text Data:\n{}\n\nEAS Protocol Data:\n{}\n\nPowered by Wags' Software ENDEC (https://github.com/wagwan-piffting-blud/ASMARA_Rust)",
-        station_name.as_str(),
+        station_name,
         title,
         originator,
         received_timestamp,
@@ -5,6 +5,9 @@
     const state = {
         streams: new Map(),
         activeAlerts: [],
+        relayQueues: new Map(),
+        pendingRelays: [],
+        attentionEvents: [],
         logs: [],
     };
 
@@ -14,6 +17,9 @@
         streamCount: document.getElementById("streamCount"),
         alertList: document.getElementById("alertList"),
         alertCount: document.getElementById("alertCount"),
+        attentionList: document.getElementById("attentionList"),
+        relayList: document.getElementById("relayList"),
+        relayCount: document.getElementById("relayCount"),
         logList: document.getElementById("logList"),
         logCount: document.getElementById("logCount"),
     };
@@ -69,8 +75,22 @@
         if (payload.active_alerts) {
             state.activeAlerts = payload.active_alerts.slice();
         }
+        if (Array.isArray(payload.relay_queues)) {
+            state.relayQueues.clear();
+            payload.relay_queues.forEach((queue) => {
+                state.relayQueues.set(queue.destination, queue);
+            });
+        }
+        if (Array.isArray(payload.pending_relays)) {
+            state.pendingRelays = payload.pending_relays.slice();
+        }
+        if (Array.isArray(payload.attention_events)) {
+            state.attentionEvents = payload.attention_events.slice();
+        }
         renderStreams();
         renderAlerts();
+        renderAttention();
+        renderRelays();
     }
 
     function applyLogs(logs) {
@@ -118,6 +138,14 @@
                 ? formatTimestamp(stream.connected_since * 1000)
                 : "—";
 
+            const levels = stream.levels
+                ? `${stream.levels.rms_dbfs.toFixed(1)} dBFS RMS / ${stream.levels.peak_dbfs.toFixed(1)} dBFS peak`
+                : "—";
+            const alarms = [
+                stream.silence_alarm ? "Silence" : null,
+                stream.clipping_alarm ? "Clipping" : null,
+            ].filter(Boolean);
+
             card.innerHTML = `
                 <div class="stream-header">
                 <div class="status-tag">${statusLabel}</div>
@@ -125,6 +153,8 @@
                 </div>
                 <div class="stream-meta">
                     <div><strong>Audio:</strong> ${receivingText}</div>
+                    <div><strong>Levels:</strong> ${levels}</div>
+                    <div><strong>Level alarms:</strong> ${alarms.length ? alarms.join(", ") : "None"}</div>
                     <div><strong>Uptime:</strong> ${uptime}</div>
                     <div><strong>Connected since:</strong> ${connectedSince}</div>
                     <div><strong>Last audio:</strong> ${lastActivity}</div>
@@ -184,6 +214,10 @@
                     <br>
                     <div><strong>Locations:</strong> ${alert.data.locations || "—"}</div>
                     <br>
+                    <div><strong>Sources:</strong> ${alert.sources?.length ? alert.sources.join(", ") : "—"}</div>
+                    <br>
+                    <div><strong>Filter:</strong> ${alert.decision ? `${alert.decision.rule || "Default Filter"} (${alert.decision.action})` : "—"}</div>
+                    <br>
                     <div><strong>Received:</strong> ${formatTimestamp(alert.received_at * 1000)}</div>
                     <br>
                     <div><strong>Expires:</strong> ${formatTimestamp(alert.expires_at * 1000)}</div>
@@ -195,6 +229,134 @@
         }
     }
 
+    const TONE_LABELS = {
+        two_tone: "EAS two-tone (853/960 Hz)",
+        nwr: "NWR 1050 Hz tone",
+    };
+
+    function renderAttention() {
+        const container = elements.attentionList;
+        container.innerHTML = "";
+        const events = state.attentionEvents
+            .filter((event) => !event.header)
+            .sort((a, b) => b.started_at - a.started_at)
+            .slice(0, 10);
+
+        if (!events.length) {
+            container.innerHTML = '<div class="empty-state">No tones heard without a header.</div>';
+            return;
+        }
+
+        for (const event of events) {
+            const card = document.createElement("article");
+            card.className = "alert-card unknown";
+            card.innerHTML = `
+                <div class="headline">${TONE_LABELS[event.tone] || event.tone}</div>
+                <div class="meta">
+                    <div><strong>Stream:</strong> ${event.stream}</div>
+                    <div><strong>Heard:</strong> ${formatTimestamp(event.started_at)}</div>
+                    <div><strong>Length:</strong> ${event.duration_secs.toFixed(1)}s</div>
+                </div>
+            `;
+            container.appendChild(card);
+        }
+    }
+
+    function describeQueuedRelay(relay) {
+        return `${relay.event_code} — ${relay.event_text} (${relay.priority}, queued ${formatTimestamp(relay.queued_at * 1000)})`;
+    }
+
+    function renderRelays() {
+        const container = elements.relayList;
+        container.innerHTML = "";
+        const held = state.pendingRelays
+            .slice()
+            .sort((a, b) => a.expires_at - b.expires_at);
+        const queues = Array.from(state.relayQueues.values()).sort((a, b) =>
+            a.destination.localeCompare(b.destination)
+        );
+        const playing = queues.filter((queue) => queue.playing).length;
+        const counts = [
+            held.length ? `${held.length} held` : null,
+            playing ? `${playing} playing` : null,
+        ].filter(Boolean);
+        elements.relayCount.textContent = counts.length ? counts.join(", ") : "Idle";
+
+        if (!held.length && !queues.length) {
+            container.innerHTML = '<div class="empty-state">No relay destinations configured.</div>';
+            return;
+        }
+
+        for (const relay of held) {
+            const card = document.createElement("article");
+            card.className = "alert-card warning";
+            card.innerHTML = `
+                <div class="event-code">${relay.event_code}</div>
+                <div class="headline">Held for ${relay.destination}: ${relay.event_text}</div>
+                <div class="meta">
+                    <div><strong>Filter:</strong> ${relay.rule || "Default Filter"}</div>
+                    <div><strong>Held:</strong> ${formatTimestamp(relay.held_at * 1000)}</div>
+                    <div><strong>Expires:</strong> ${formatTimestamp(relay.expires_at * 1000)} (then ${relay.default_action})</div>
+                </div>
+                <div class="relay-actions">
+                    <button type="button" class="custom-button" data-relay-id="${relay.id}" data-relay-action="approve">Approve</button>
+                    <button type="button" class="custom-button" data-relay-id="${relay.id}" data-relay-action="cancel">Cancel</button>
+                </div>
+            `;
+            container.appendChild(card);
+        }
+
+        for (const queue of queues) {
+            const card = document.createElement("article");
+            card.className = `stream-card ${queue.playing ? "online" : ""}`.trim();
+            const pending = queue.pending.length
+                ? queue.pending.map((relay) => `<li>${describeQueuedRelay(relay)}</li>`).join("")
+                : "<li>None</li>";
+            card.innerHTML = `
+                <div class="stream-header">
+                    <div class="status-tag">${queue.playing ? "Playing" : "Idle"}</div>
+                    <div class="stream-url">${queue.destination}</div>
+                </div>
+                <div class="stream-meta">
+                    <div><strong>Now:</strong> ${queue.playing ? describeQueuedRelay(queue.playing) : "—"}</div>
+                    <div><strong>Queued:</strong><ul>${pending}</ul></div>
+                </div>
+            `;
+            container.appendChild(card);
+        }
+    }
+
+    async function decideHeldRelay(id, action) {
+        try {
+            const protocol = window.location.protocol === "https:" ? "https" : "http";
+            const response = await fetch(`${protocol}://${window.API_BASE}/api/relays/${id}/${action}`, {
+                method: "POST",
+                headers: {
+                    Accept: "application/json",
+                    Authorization: `Bearer ${window.TOKEN}`,
+                },
+            });
+            if (!response.ok) {
+                const body = await response.json().catch(() => null);
+                throw new Error(body?.error || `HTTP ${response.status}`);
+            }
+            return true;
+        } catch (err) {
+            console.error(`Failed to ${action} held relay ${id}:`, err);
+            window.alert(`Could not ${action} the held relay: ${err.message}`);
+            return false;
+        }
+    }
+
+    elements.relayList.addEventListener("click", (event) => {
+        const button = event.target.closest("button[data-relay-action]");
+        if (!button) return;
+        button.disabled = true;
+        decideHeldRelay(button.dataset.relayId, button.dataset.relayAction).then((ok) => {
+            button.disabled = !ok;
+        });
+    });
+
     function renderLogs() {
         const container = elements.logList;
         container.innerHTML = "";
@@ -299,6 +461,24 @@
                         renderAlerts();
                     }
                     break;
+                case "RelayQueue":
+                    if (payload.payload?.destination) {
+                        state.relayQueues.set(payload.payload.destination, payload.payload);
+                        renderRelays();
+                    }
+                    break;
+                case "PendingRelays":
+                    if (Array.isArray(payload.payload)) {
+                        state.pendingRelays = payload.payload.slice();
+                        renderRelays();
+                    }
+                    break;
+                case "Attention":
+                    if (payload.payload) {
+                        state.attentionEvents = [payload.payload, ...state.attentionEvents].slice(0, 50);
+                        renderAttention();
+                    }
+                    break;
                 default:
                     console.warn("Unhandled WS message type", payload.type);
             }
@@ -139,6 +139,15 @@ if(!isset($_SESSION['authed'])) { $_SESSION["redirect"] = $_GET["redirect"] ?? n
                 </h2>
                 <a class="smalltext" href="archive.php">(click here to view all archived alerts)</a>
                 <div id="alertList" class="section-scroll"></div>
+                <h3>Tones Without a Header</h3>
+                <div id="attentionList" class="section-scroll"></div>
+            </section>
+            <section id="relaySection">
+                <h2>
+                    Relays
+                    <span id="relayCount" class="pill">Idle</span>
+                </h2>
+                <div id="relayList" class="section-scroll"></div>
             </section>
             <section id="logSection">
                 <h2>